@@ -1,2 +1,4 @@
 pub mod connector;
-pub mod processor;
\ No newline at end of file
+pub mod processor;
+pub mod registry;
+pub mod tracker;
\ No newline at end of file
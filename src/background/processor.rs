@@ -21,6 +21,8 @@ pub struct RavalinkMessage {
 #[derive(Clone, Debug)]
 pub enum RavalinkIPC {
     Message(RavalinkMessage),
+    /// The player for this guild was destroyed; drop its routing state.
+    ReleaseGuild(NonZero<u64>),
 }
 
 impl RavalinkIPC {
@@ -101,6 +103,7 @@ impl GuildIdProvider for Message {
 pub async fn init_processor(
     mut rx: Receiver<RavalinkIPC>,
     mut global_tx: Sender<RavalinkIPC>,
+    firehose_tx: Sender<Message>,
     consumer: Arc<StreamConsumer>,
     mut producer: FutureProducer,
     config: RavalinkConfig,
@@ -113,15 +116,23 @@ pub async fn init_processor(
             ipc_message = rx.recv() => {
                 match ipc_message {
                     Ok(RavalinkIPC::Message(m)) => {
+                        // Node messages are re-broadcast on the global channel; only
+                        // messages sent by this bot carry a response channel.
+                        let Some(response_tx) = &m.response_tx else {
+                            continue;
+                        };
                         if let Message::Pong { id: _ } = &m.message {
                             debug!("Skipping Pong message, not sending to Kafka.");
                         } else {
                             if let Some(guild_id) = m.guild_id {
-                                guild_id_to_tx.insert(guild_id, m.response_tx.clone().unwrap());
+                                guild_id_to_tx.insert(guild_id, response_tx.clone());
                             }
                             send_message(&m.message, &config.kafka_topic, &mut producer).await;
                         }
                     },
+                    Ok(RavalinkIPC::ReleaseGuild(guild_id)) => {
+                        guild_id_to_tx.remove(&guild_id);
+                    },
                     Err(tokio::sync::broadcast::error::RecvError::Closed) => {
                         error!("IPC channel closed, stopping processor.");
                         break;
@@ -143,6 +154,9 @@ pub async fn init_processor(
 
                         match serde_json::from_slice::<Message>(payload.unwrap()) {
                             Ok(parsed_message) => {
+                                if firehose_tx.receiver_count() > 0 {
+                                    let _ = firehose_tx.send(parsed_message.clone());
+                                }
                                 parse_message(parsed_message, &mut guild_id_to_tx, &mut global_tx).await;
                             },
                             Err(e) => error!("Failed to parse message: {}", e),
@@ -0,0 +1,68 @@
+use crate::background::processor::RavalinkIPC;
+use crate::errors::DeliveryFailedSnafu;
+use crate::models::CommandKind;
+use crate::{PlayerError, PlayerObject};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The players of one client, by guild.
+pub(crate) type Registry = RwLock<HashMap<NonZero<u64>, PlayerObject>>;
+
+impl PlayerObject {
+    /// Removes the player from the client's registry, unless another player
+    /// has already taken its place, and releases its response channel.
+    pub(crate) async fn unregister(&self) -> Result<(), PlayerError> {
+        let guild_id = self.inner.guild_id;
+        let registry = self.inner.registry.upgrade();
+        let mut players = match &registry {
+            Some(registry) => Some(registry.write().await),
+            None => None,
+        };
+        if let Some(players) = players.as_mut() {
+            match players.get(&guild_id) {
+                Some(player) if Arc::ptr_eq(&player.inner, &self.inner) => {
+                    players.remove(&guild_id);
+                }
+                // The channel belongs to the player that replaced this one.
+                Some(_) => return Ok(()),
+                None => {}
+            }
+        }
+
+        // Sent while the registry is still locked, so that a player created
+        // in this one's place cannot send anything ahead of the release.
+        self.inner.bg_com_tx
+            .send(RavalinkIPC::ReleaseGuild(guild_id))
+            .context(DeliveryFailedSnafu { guild_id: Some(guild_id), command: CommandKind::Destroy })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeNode;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn a_stale_player_leaves_its_replacement_registered() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let registry: Arc<Registry> = Arc::default();
+        let guild_id = NonZero::new(1).unwrap();
+        let create = || PlayerObject::new(guild_id, node.tx.clone(), Arc::new(Vec::new()), None, Arc::downgrade(&registry));
+
+        let old = create().await.unwrap();
+        registry.write().await.insert(guild_id, old.clone());
+        old.unregister().await.unwrap();
+        assert!(registry.read().await.is_empty());
+
+        let new = create().await.unwrap();
+        registry.write().await.insert(guild_id, new.clone());
+        old.unregister().await.unwrap();
+        let players = registry.read().await;
+        assert!(Arc::ptr_eq(&players[&guild_id].inner, &new.inner));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::background::processor::RavalinkIPC;
+use crate::events::PlayerEvent;
+use crate::helpers::get_unix_timestamp;
+use crate::PlayerObject;
+use log::{debug, error};
+use ravalink_interconnect::protocol::Message;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, MissedTickBehavior};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Follows the player's messages until it is shut down or every handle to it
+/// is dropped. The tracker only holds a weak reference between messages, so
+/// it does not keep the player alive itself.
+pub fn spawn_player_tracker(player: &PlayerObject) {
+    let mut rx = player.inner.tx.subscribe();
+    let guild_id = player.inner.guild_id;
+    let shutdown = player.inner.shutdown.clone();
+    let subscribers = player.inner.subscribers.clone();
+    let weak = player.downgrade();
+
+    tokio::spawn(async move {
+        let mut poll = interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut save = interval(SAVE_INTERVAL);
+        save.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut last_saved = None;
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Ok(RavalinkIPC::Message(ravalink_message)) => {
+                        let (event, timestamp) = match &ravalink_message.message {
+                            Message::Event(event) if event.guild_id == guild_id => {
+                                (PlayerEvent::decode(event), event.timestamp)
+                            }
+                            _ => continue,
+                        };
+                        let Some(player) = weak.upgrade() else {
+                            break;
+                        };
+
+                        let ended = player.inner.state.borrow().track.clone();
+                        let now = get_unix_timestamp();
+                        player.inner.state.send_modify(|s| s.apply_event(&event, timestamp, now));
+
+                        if let PlayerEvent::TrackEnd { reason, .. } = &event {
+                            if reason.may_start_next() {
+                                let next = player.internal();
+                                let reason = reason.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = next.advance(ended, &reason).await {
+                                        error!("Failed to advance queue for guild {}: {}", guild_id, e);
+                                    }
+                                });
+                            }
+                        }
+
+                        player.inner.subscribers.publish(guild_id, event);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Tracker for guild {} skipped {} messages", guild_id, skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+
+                _ = poll.tick() => {
+                    let Some(player) = weak.upgrade() else {
+                        break;
+                    };
+                    let internal = player.internal();
+                    internal.crossfade_if_due().await;
+                    internal.disconnect_if_idle().await;
+                }
+
+                _ = save.tick() => {
+                    let Some(player) = weak.upgrade() else {
+                        break;
+                    };
+                    last_saved = player.persist(last_saved.take()).await;
+                }
+
+                _ = shutdown.notified() => break,
+            }
+        }
+
+        subscribers.close();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::managers::queue_manager::QueueManager;
+    use crate::models::{LoopMode, QueuedTrack};
+    use crate::test_support::{emit, play_reply, track, FakeNode};
+    use futures::StreamExt;
+    use ravalink_interconnect::protocol::{Command, EventType};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn tracker_stops_once_every_handle_is_dropped() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        let mut events = player.events();
+
+        drop(player);
+        // The tracker closes the event streams on its way out.
+        let ended = tokio::time::timeout(Duration::from_secs(2), events.next()).await;
+        assert!(matches!(ended, Ok(None)));
+    }
+
+    /// Ends track `a` with `reason` while `b` is queued. Returns what the
+    /// tracker sent in response and what is left in the queue.
+    async fn end_track(mode: LoopMode, reason: &str) -> (Vec<Command>, Vec<QueuedTrack>) {
+        let node = FakeNode::spawn(play_reply);
+        let player = node.player().await;
+        player.inner.state.send_modify(|s| {
+            s.loop_mode = mode;
+            s.track = Some(track("a"));
+        });
+        player.enqueue("b".to_string().into()).await.unwrap();
+
+        emit(&player, EventType::TrackEnd, json!({ "track": track("a"), "reason": reason }));
+        (node.wait_for(1).await, player.list().await)
+    }
+
+    fn played(commands: &[Command]) -> Vec<&str> {
+        commands.iter().filter_map(|c| match c {
+            Command::Play { url, .. } => Some(url.as_str()),
+            _ => None,
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn a_track_that_failed_to_load_is_skipped_in_every_loop_mode() {
+        for mode in [LoopMode::Off, LoopMode::Track, LoopMode::Queue, LoopMode::Count(2)] {
+            let (sent, queue) = end_track(mode, "load_failed").await;
+            assert_eq!(played(&sent), ["b"], "{:?}", mode);
+            assert!(queue.is_empty(), "{:?}", mode);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_finished_track_follows_the_loop_mode() {
+        let (sent, queue) = end_track(LoopMode::Track, "finished").await;
+        assert_eq!(played(&sent), ["a"]);
+        assert_eq!(queue.len(), 1);
+
+        let (sent, queue) = end_track(LoopMode::Queue, "finished").await;
+        assert_eq!(played(&sent), ["b"]);
+        assert_eq!(queue.iter().map(|t| t.url.as_str()).collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn a_replaced_track_does_not_start_the_next_one() {
+        let (sent, queue) = end_track(LoopMode::Off, "replaced").await;
+        assert!(sent.is_empty());
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn events_update_the_watched_state() {
+        let node = FakeNode::spawn(play_reply);
+        let player = node.player().await;
+        let mut state = player.watch_state();
+
+        emit(&player, EventType::TrackStart, json!({ "track": track("a") }));
+        tokio::time::timeout(Duration::from_secs(1), state.changed()).await.unwrap().unwrap();
+        assert_eq!(player.state().track, Some(track("a")));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::errors::InvalidArgumentSnafu;
+use crate::models::{CommandKind, PlayOptions};
+use crate::{PlayerError, PlayerObject};
+use ravalink_interconnect::protocol::{Command, Response};
+use serde_json::Value;
+use std::num::NonZero;
+use std::time::Duration;
+
+/// Commands sent to the node as one request. Build it with
+/// [`PlayerObject::batch`].
+#[must_use = "a batch does nothing until it is sent"]
+pub struct CommandBatch<'a> {
+    player: &'a PlayerObject,
+    commands: Vec<Command>,
+    voice_channel_id: Option<NonZero<u64>>,
+}
+
+/// The outcome of a sent batch.
+#[derive(Debug)]
+pub struct BatchReport {
+    /// Whether the node ran the batch as one all-or-nothing request.
+    pub atomic: bool,
+    /// One result per step that was sent, in order. When the node could not
+    /// run the batch atomically, steps after the first failure are not sent.
+    pub steps: Vec<Result<Response, PlayerError>>,
+    len: usize,
+}
+
+impl BatchReport {
+    /// Whether every step was sent and succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.steps.len() == self.len && self.steps.iter().all(|s| s.is_ok())
+    }
+}
+
+impl PlayerObject {
+    pub fn batch(&self) -> CommandBatch<'_> {
+        CommandBatch { player: self, commands: Vec::new(), voice_channel_id: None }
+    }
+}
+
+impl<'a> CommandBatch<'a> {
+    pub fn command(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Voice channel sent with the request, needed by `Command::Connect`.
+    pub fn voice_channel(mut self, voice_channel_id: NonZero<u64>) -> Self {
+        self.voice_channel_id = Some(voice_channel_id);
+        self
+    }
+
+    pub fn play(self, url: impl Into<String>, options: PlayOptions) -> Self {
+        self.command(Command::Play {
+            url: url.into(),
+            start_time: options.start_time.map(|t| t.as_millis() as u64),
+            end_time: options.end_time.map(|t| t.as_millis() as u64),
+            no_replace: options.no_replace,
+        })
+    }
+
+    pub fn set_volume(self, volume: f32) -> Self {
+        self.command(Command::SetVolume { volume })
+    }
+
+    pub fn seek(self, position: Duration) -> Self {
+        self.command(Command::SeekToPosition { position: position.as_millis() as u64 })
+    }
+
+    pub fn pause(self) -> Self {
+        self.command(Command::Pause)
+    }
+
+    pub fn resume(self) -> Self {
+        self.command(Command::Resume)
+    }
+
+    /// Sends the batch as one request. If the node does not support batches,
+    /// the commands are sent one by one, stopping at the first failure.
+    ///
+    /// Every step is validated and passed through the middleware chain on its
+    /// own, so layers see the inner commands rather than the batch.
+    pub async fn send(self) -> Result<BatchReport, PlayerError> {
+        let player = self.player;
+        let len = self.commands.len();
+        if len == 0 {
+            return InvalidArgumentSnafu {
+                guild_id: player.inner.guild_id,
+                command: CommandKind::Batch,
+                reason: "a batch needs at least one command",
+            }.fail();
+        }
+        for command in &self.commands {
+            validate(player, command)?;
+        }
+
+        let mut contexts = Vec::with_capacity(len);
+        for command in self.commands {
+            contexts.push(player.before_send(command, self.voice_channel_id).await?);
+        }
+
+        // Fades still running must not overwrite the volume set by the batch.
+        if contexts.iter().any(|c| matches!(c.command, Command::SetVolume { .. })) {
+            player.inner.fades.lock().await.generation += 1;
+        }
+
+        let batch = Command::Batch { commands: contexts.iter().map(|c| c.command.clone()).collect() };
+        let (atomic, steps) = match player.send_command(batch, self.voice_channel_id).await {
+            Ok(response) => {
+                let mut steps = Vec::with_capacity(len);
+                for (context, step) in contexts.iter().zip(split_batch_response(&response, len)) {
+                    steps.push(player.after_send(context, Ok(step)).await);
+                }
+                (true, steps)
+            }
+            Err(PlayerError::Unsupported { .. }) => {
+                let mut steps = Vec::with_capacity(len);
+                for context in &contexts {
+                    let result = player.send_command(context.command.clone(), context.voice_channel_id).await;
+                    let result = player.after_send(context, result).await;
+                    let failed = result.is_err();
+                    steps.push(result);
+                    if failed {
+                        break;
+                    }
+                }
+                (false, steps)
+            }
+            Err(e) => {
+                // The batch failed as a whole, so its error is the one returned.
+                for context in &contexts {
+                    let _ = player.after_send(context, Err(e.clone())).await;
+                }
+                return Err(e);
+            }
+        };
+
+        // Later fades return to the last volume the batch actually set.
+        let volume = contexts.iter().zip(&steps).rev().find_map(|(context, step)| match context.command {
+            Command::SetVolume { volume } if step.is_ok() => Some(volume),
+            _ => None,
+        });
+        if let Some(volume) = volume {
+            player.inner.fades.lock().await.volume = volume;
+        }
+
+        Ok(BatchReport { atomic, steps, len })
+    }
+}
+
+fn validate(player: &PlayerObject, command: &Command) -> Result<(), PlayerError> {
+    match command {
+        Command::SetVolume { volume } => player.validate_volume(*volume),
+        Command::Play { url, start_time, end_time, no_replace } => {
+            let options = PlayOptions {
+                start_time: start_time.map(Duration::from_millis),
+                end_time: end_time.map(Duration::from_millis),
+                no_replace: *no_replace,
+            };
+            player.validate_play(url, &options)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Splits the response to a batch into one response per step. The node
+/// answers a batch with an array holding the data of each step.
+pub(crate) fn split_batch_response(response: &Response, len: usize) -> Vec<Response> {
+    let data = response.data.as_array();
+    (0..len)
+        .map(|i| Response {
+            job_id: response.job_id.clone(),
+            guild_id: response.guild_id,
+            timestamp: response.timestamp,
+            error: None,
+            data: data.and_then(|d| d.get(i)).cloned().unwrap_or(Value::Null),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeNode;
+    use serde_json::json;
+
+    fn batch_response(data: Value) -> Response {
+        Response {
+            job_id: "job".to_string(),
+            guild_id: NonZero::new(7).unwrap(),
+            timestamp: 1234,
+            error: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn splits_one_response_per_step() {
+        let steps = split_batch_response(&batch_response(json!([{ "a": 1 }, null, 3])), 3);
+        let data: Vec<Value> = steps.iter().map(|s| s.data.clone()).collect();
+        assert_eq!(data, vec![json!({ "a": 1 }), Value::Null, json!(3)]);
+        assert!(steps.iter().all(|s| s.job_id == "job" && s.timestamp == 1234 && s.error.is_none()));
+    }
+
+    #[test]
+    fn missing_or_malformed_step_data_is_null() {
+        let short = split_batch_response(&batch_response(json!([1])), 3);
+        assert_eq!(short.len(), 3);
+        assert!(short[1].data.is_null() && short[2].data.is_null());
+
+        let object = split_batch_response(&batch_response(json!({ "ok": true })), 2);
+        assert!(object.iter().all(|s| s.data.is_null()));
+    }
+
+    #[tokio::test]
+    async fn sends_one_request_when_the_node_supports_batches() {
+        let node = FakeNode::spawn(|command| match command {
+            Command::Batch { commands } => Ok(json!(vec![Value::Null; commands.len()])),
+            _ => Err("unexpected"),
+        });
+        let player = node.player().await;
+
+        let report = player.batch().set_volume(0.5).pause().send().await.unwrap();
+        assert!(report.atomic);
+        assert!(report.is_complete());
+        assert_eq!(node.received().len(), 1);
+        assert!(player.state().paused);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_single_commands_when_batches_are_unsupported() {
+        let node = FakeNode::spawn(|command| match command {
+            Command::Batch { .. } => Err("unsupported"),
+            _ => Ok(Value::Null),
+        });
+        let player = node.player().await;
+
+        let report = player.batch().set_volume(0.5).pause().send().await.unwrap();
+        assert!(!report.atomic);
+        assert!(report.is_complete());
+        let received = node.received();
+        assert!(matches!(
+            received.as_slice(),
+            [Command::Batch { .. }, Command::SetVolume { .. }, Command::Pause]
+        ));
+    }
+
+    #[tokio::test]
+    async fn fallback_stops_at_the_first_failure() {
+        let node = FakeNode::spawn(|command| match command {
+            Command::Batch { .. } => Err("unsupported"),
+            Command::Pause => Err("rejected"),
+            _ => Ok(Value::Null),
+        });
+        let player = node.player().await;
+
+        let report = player.batch().pause().resume().send().await.unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(report.steps.len(), 1);
+        assert!(matches!(report.steps[0], Err(PlayerError::Rejected { .. })));
+        assert_eq!(node.received().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn empty_batches_are_rejected() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+
+        let result = player.batch().send().await;
+        assert!(matches!(result, Err(PlayerError::InvalidArgument { .. })));
+        assert!(node.received().is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::errors::ShutdownSnafu;
+use crate::middleware::CommandOrigin;
+use crate::models::CommandKind;
+use crate::{PlayerError, PlayerObject};
+use ravalink_interconnect::protocol::{Command, Response};
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long a coalescing command waits for newer commands of its kind.
+pub(crate) const COALESCE_WINDOW: Duration = Duration::from_millis(50);
+
+type Waiter = oneshot::Sender<Result<Response, PlayerError>>;
+
+struct PendingCommand {
+    command: Command,
+    voice_channel_id: Option<NonZero<u64>>,
+    /// `User` if any caller folded into the window was a user.
+    origin: CommandOrigin,
+    waiters: Vec<Waiter>,
+}
+
+/// Commands waiting out their coalescing window, one per kind.
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    pending: Mutex<HashMap<CommandKind, PendingCommand>>,
+}
+
+impl Coalescer {
+    /// Replaces the pending command of this kind, or starts a new window.
+    /// Returns whether a new window was started.
+    fn join(
+        &self,
+        kind: CommandKind,
+        command: Command,
+        voice_channel_id: Option<NonZero<u64>>,
+        origin: CommandOrigin,
+        waiter: Waiter,
+    ) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&kind) {
+            Some(entry) => {
+                entry.command = command;
+                entry.voice_channel_id = voice_channel_id;
+                if origin == CommandOrigin::User {
+                    entry.origin = origin;
+                }
+                entry.waiters.push(waiter);
+                false
+            }
+            None => {
+                pending.insert(kind, PendingCommand { command, voice_channel_id, origin, waiters: vec![waiter] });
+                true
+            }
+        }
+    }
+
+    fn take(&self, kind: CommandKind) -> Option<PendingCommand> {
+        self.pending.lock().unwrap().remove(&kind)
+    }
+}
+
+impl PlayerObject {
+    /// Sends `command` after `COALESCE_WINDOW`, unless a newer command of the
+    /// same kind arrives first. Only the latest command is sent, and every
+    /// caller receives its result.
+    pub(crate) async fn send_coalesced(
+        &self,
+        command: Command,
+        voice_channel_id: Option<NonZero<u64>>,
+    ) -> Result<Response, PlayerError> {
+        let kind = CommandKind::from(&command);
+        let (tx, rx) = oneshot::channel();
+
+        // The window runs in its own task so that a cancelled caller does not
+        // strand the callers folded into it.
+        if self.inner.coalescer.join(kind, command, voice_channel_id, self.origin, tx) {
+            let player = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(COALESCE_WINDOW).await;
+                let Some(pending) = player.inner.coalescer.take(kind) else {
+                    return;
+                };
+                let player = PlayerObject { origin: pending.origin, ..player };
+                let result = player.run_command(pending.command, pending.voice_channel_id).await;
+                for waiter in pending.waiters {
+                    let _ = waiter.send(result.clone());
+                }
+            });
+        }
+
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => ShutdownSnafu { guild_id: Some(self.inner.guild_id), command: kind }.fail(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::managers::track_manager::TrackManager;
+    use crate::test_support::FakeNode;
+    use serde_json::Value;
+    use std::time::Instant;
+
+    fn volumes(commands: &[Command]) -> Vec<f32> {
+        commands.iter().filter_map(|c| match c {
+            Command::SetVolume { volume } => Some(*volume),
+            _ => None,
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn a_command_waits_out_the_window() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+
+        let started = Instant::now();
+        player.set_volume(0.5).await.unwrap();
+        assert!(started.elapsed() >= COALESCE_WINDOW);
+    }
+
+    #[tokio::test]
+    async fn only_the_latest_command_in_a_window_is_sent() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+
+        let (first, second, third) = tokio::join!(
+            player.set_volume(0.2),
+            player.set_volume(0.4),
+            player.set_volume(0.6),
+        );
+        assert_eq!((first.unwrap(), second.unwrap(), third.unwrap()), (0.6, 0.6, 0.6));
+        assert_eq!(volumes(&node.received()), [0.6]);
+
+        // A command after the window has closed opens a new one.
+        player.set_volume(0.8).await.unwrap();
+        assert_eq!(volumes(&node.received()), [0.6, 0.8]);
+    }
+
+    #[tokio::test]
+    async fn every_caller_in_a_window_gets_the_error() {
+        let node = FakeNode::spawn(|_| Err("boom"));
+        let player = node.player().await;
+
+        let (first, second) = tokio::join!(player.set_volume(0.2), player.set_volume(0.4));
+        assert!(matches!(first, Err(PlayerError::Rejected { .. })));
+        assert!(matches!(second, Err(PlayerError::Rejected { .. })));
+        assert_eq!(node.received().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn kinds_are_coalesced_separately() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+
+        let (volume, seek) = tokio::join!(player.set_volume(0.2), player.seek(Duration::from_secs(5)));
+        assert!(volume.is_ok() && seek.is_ok());
+        assert_eq!(node.received().len(), 2);
+    }
+}
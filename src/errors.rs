@@ -0,0 +1,164 @@
+use crate::background::processor::RavalinkIPC;
+use crate::models::CommandKind;
+use snafu::Snafu;
+use std::num::NonZero;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::SendError;
+
+/// Sources are kept behind an `Arc` so that one result can be handed to
+/// every caller that shares it.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum PlayerError {
+    #[snafu(display("Timed out waiting for the {command} response (job {job_id})"))]
+    Timeout {
+        guild_id: Option<NonZero<u64>>,
+        job_id: String,
+        command: CommandKind,
+    },
+    #[snafu(display("Failed to deliver the {command} request to the processor"))]
+    DeliveryFailed {
+        guild_id: Option<NonZero<u64>>,
+        command: CommandKind,
+        #[snafu(source(from(SendError<RavalinkIPC>, Arc::new)))]
+        source: Arc<SendError<RavalinkIPC>>,
+    },
+    #[snafu(display("Node rejected {command} for guild {guild_id} (job {job_id}): {reason}"))]
+    Rejected {
+        guild_id: NonZero<u64>,
+        job_id: String,
+        command: CommandKind,
+        reason: String,
+    },
+    #[snafu(display("Player for guild {guild_id} is not connected, cannot {command}"))]
+    NotConnected {
+        guild_id: NonZero<u64>,
+        command: CommandKind,
+    },
+    #[snafu(display("Node does not support {command} (job {job_id})"))]
+    Unsupported {
+        guild_id: NonZero<u64>,
+        job_id: String,
+        command: CommandKind,
+    },
+    #[snafu(display("Invalid argument for {command}: {reason}"))]
+    InvalidArgument {
+        guild_id: NonZero<u64>,
+        command: CommandKind,
+        reason: String,
+    },
+    #[snafu(display("Could not decode the {command} response (job {job_id}): {source}"))]
+    InvalidResponse {
+        guild_id: NonZero<u64>,
+        job_id: String,
+        command: CommandKind,
+        #[snafu(source(from(serde_json::Error, Arc::new)))]
+        source: Arc<serde_json::Error>,
+    },
+    #[snafu(display("{command} was denied for guild {guild_id}: {reason}"))]
+    Denied {
+        guild_id: NonZero<u64>,
+        command: CommandKind,
+        reason: String,
+    },
+    #[snafu(display("{command} for guild {guild_id} was rate limited, retry after {retry_after:?}"))]
+    RateLimited {
+        guild_id: NonZero<u64>,
+        command: CommandKind,
+        retry_after: Duration,
+    },
+    #[snafu(display("Ravalink is shutting down, {command} was not completed"))]
+    Shutdown {
+        guild_id: Option<NonZero<u64>>,
+        command: CommandKind,
+    },
+}
+
+impl PlayerError {
+    /// Whether sending the same command again may succeed. A failed delivery
+    /// is not: it means the processor has stopped.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, PlayerError::Timeout { .. } | PlayerError::RateLimited { .. })
+    }
+
+    pub fn guild_id(&self) -> Option<NonZero<u64>> {
+        match self {
+            PlayerError::Timeout { guild_id, .. }
+            | PlayerError::DeliveryFailed { guild_id, .. }
+            | PlayerError::Shutdown { guild_id, .. } => *guild_id,
+            PlayerError::Rejected { guild_id, .. }
+            | PlayerError::NotConnected { guild_id, .. }
+            | PlayerError::Unsupported { guild_id, .. }
+            | PlayerError::InvalidArgument { guild_id, .. }
+            | PlayerError::InvalidResponse { guild_id, .. }
+            | PlayerError::Denied { guild_id, .. }
+            | PlayerError::RateLimited { guild_id, .. } => Some(*guild_id),
+        }
+    }
+
+    pub fn command(&self) -> CommandKind {
+        match self {
+            PlayerError::Timeout { command, .. }
+            | PlayerError::DeliveryFailed { command, .. }
+            | PlayerError::Rejected { command, .. }
+            | PlayerError::NotConnected { command, .. }
+            | PlayerError::Unsupported { command, .. }
+            | PlayerError::InvalidArgument { command, .. }
+            | PlayerError::InvalidResponse { command, .. }
+            | PlayerError::Denied { command, .. }
+            | PlayerError::RateLimited { command, .. }
+            | PlayerError::Shutdown { command, .. } => *command,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum StoreError {
+    #[snafu(display("State store I/O failed on {}: {source}", path.display()))]
+    StoreIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not (de)serialize a player snapshot: {source}"))]
+    StoreSerialization { source: serde_json::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snafu::ResultExt;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn only_transient_failures_are_retryable() {
+        let guild_id = NonZero::new(1).unwrap();
+        let (tx, rx) = broadcast::channel::<RavalinkIPC>(1);
+        drop(rx);
+        let delivery = tx
+            .send(RavalinkIPC::ReleaseGuild(guild_id))
+            .context(DeliveryFailedSnafu { guild_id: Some(guild_id), command: CommandKind::Play })
+            .unwrap_err();
+
+        let retryable = [
+            PlayerError::Timeout { guild_id: Some(guild_id), job_id: "job".into(), command: CommandKind::Play },
+            PlayerError::RateLimited { guild_id, command: CommandKind::Play, retry_after: Duration::from_secs(1) },
+        ];
+        let permanent = [
+            delivery,
+            PlayerError::Shutdown { guild_id: Some(guild_id), command: CommandKind::Play },
+            PlayerError::Unsupported { guild_id, job_id: "job".into(), command: CommandKind::Play },
+            PlayerError::Rejected { guild_id, job_id: "job".into(), command: CommandKind::Play, reason: "no".into() },
+        ];
+        for error in retryable {
+            assert!(error.is_retryable(), "{}", error);
+        }
+        for error in permanent {
+            assert!(!error.is_retryable(), "{}", error);
+            assert_eq!(error.guild_id(), Some(guild_id));
+            assert_eq!(error.command(), CommandKind::Play);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::models::{IdleReason, TrackInfo};
+use ravalink_interconnect::protocol::{Event, EventType};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// An event of one player, decoded from what the node sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerEvent {
+    TrackStart { track: Option<TrackInfo> },
+    TrackEnd { track: Option<TrackInfo>, reason: TrackEndReason },
+    /// The track has not produced audio for `threshold`.
+    TrackStuck { track: Option<TrackInfo>, threshold: Duration },
+    TrackException { track: Option<TrackInfo>, message: String },
+    PositionUpdate { position: Duration },
+    /// The voice connection was closed by Discord.
+    VoiceClosed { code: Option<u16>, reason: Option<String> },
+    Error { message: String },
+    /// The player is about to leave its voice channel because of its idle policy.
+    AutoDisconnect { reason: IdleReason },
+    /// The subscription fell behind and `lost` events were dropped. Sent to
+    /// every subscription regardless of its filter.
+    Lagged { lost: u64 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackEndReason {
+    Finished,
+    LoadFailed,
+    Stopped,
+    /// Another track started in its place, e.g. by `play` or a crossfade.
+    Replaced,
+    Cleanup,
+    #[serde(other)]
+    Unknown,
+}
+
+impl TrackEndReason {
+    /// Whether the queue should start its next track.
+    pub fn may_start_next(&self) -> bool {
+        matches!(self, TrackEndReason::Finished | TrackEndReason::LoadFailed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlayerEventKind {
+    TrackStart,
+    TrackEnd,
+    TrackStuck,
+    TrackException,
+    PositionUpdate,
+    VoiceClosed,
+    Error,
+    AutoDisconnect,
+    Lagged,
+}
+
+/// The kinds of events a subscription receives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventFilter(u16);
+
+impl EventFilter {
+    pub fn all() -> Self {
+        EventFilter(u16::MAX)
+    }
+
+    pub fn none() -> Self {
+        EventFilter(0)
+    }
+
+    pub fn with(self, kind: PlayerEventKind) -> Self {
+        EventFilter(self.0 | Self::bit(kind))
+    }
+
+    pub fn contains(&self, kind: PlayerEventKind) -> bool {
+        self.0 & Self::bit(kind) != 0
+    }
+
+    fn bit(kind: PlayerEventKind) -> u16 {
+        1 << kind as u16
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        EventFilter::all()
+    }
+}
+
+impl From<PlayerEventKind> for EventFilter {
+    fn from(kind: PlayerEventKind) -> Self {
+        EventFilter::none().with(kind)
+    }
+}
+
+impl FromIterator<PlayerEventKind> for EventFilter {
+    fn from_iter<I: IntoIterator<Item = PlayerEventKind>>(kinds: I) -> Self {
+        kinds.into_iter().fold(EventFilter::none(), EventFilter::with)
+    }
+}
+
+#[derive(Deserialize)]
+struct TrackData {
+    #[serde(default)]
+    track: Option<TrackInfo>,
+}
+
+#[derive(Deserialize)]
+struct TrackEndData {
+    #[serde(default)]
+    track: Option<TrackInfo>,
+    reason: TrackEndReason,
+}
+
+#[derive(Deserialize)]
+struct TrackStuckData {
+    #[serde(default)]
+    track: Option<TrackInfo>,
+    #[serde(with = "crate::helpers::millis")]
+    threshold: Duration,
+}
+
+#[derive(Deserialize)]
+struct MessageData {
+    #[serde(default)]
+    track: Option<TrackInfo>,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct PositionData {
+    #[serde(with = "crate::helpers::millis")]
+    position: Duration,
+}
+
+#[derive(Deserialize)]
+struct VoiceClosedData {
+    #[serde(default)]
+    code: Option<u16>,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+impl PlayerEvent {
+    pub fn kind(&self) -> PlayerEventKind {
+        match self {
+            PlayerEvent::TrackStart { .. } => PlayerEventKind::TrackStart,
+            PlayerEvent::TrackEnd { .. } => PlayerEventKind::TrackEnd,
+            PlayerEvent::TrackStuck { .. } => PlayerEventKind::TrackStuck,
+            PlayerEvent::TrackException { .. } => PlayerEventKind::TrackException,
+            PlayerEvent::PositionUpdate { .. } => PlayerEventKind::PositionUpdate,
+            PlayerEvent::VoiceClosed { .. } => PlayerEventKind::VoiceClosed,
+            PlayerEvent::Error { .. } => PlayerEventKind::Error,
+            PlayerEvent::AutoDisconnect { .. } => PlayerEventKind::AutoDisconnect,
+            PlayerEvent::Lagged { .. } => PlayerEventKind::Lagged,
+        }
+    }
+
+    /// Decodes a node event. Payloads that don't match the protocol are
+    /// reported as `PlayerEvent::Error`.
+    pub(crate) fn decode(event: &Event) -> Self {
+        let decoded = match event.event_type {
+            EventType::TrackStart => {
+                data(event).map(|d: TrackData| PlayerEvent::TrackStart { track: d.track })
+            }
+            EventType::TrackEnd => data(event).map(|d: TrackEndData| PlayerEvent::TrackEnd {
+                track: d.track,
+                reason: d.reason,
+            }),
+            EventType::TrackStuck => data(event).map(|d: TrackStuckData| PlayerEvent::TrackStuck {
+                track: d.track,
+                threshold: d.threshold,
+            }),
+            EventType::TrackException => data(event).map(|d: MessageData| PlayerEvent::TrackException {
+                track: d.track,
+                message: d.message,
+            }),
+            EventType::PositionUpdate => {
+                data(event).map(|d: PositionData| PlayerEvent::PositionUpdate { position: d.position })
+            }
+            EventType::VoiceClosed => data(event).map(|d: VoiceClosedData| PlayerEvent::VoiceClosed {
+                code: d.code,
+                reason: d.reason,
+            }),
+            EventType::ErrorOccurred => {
+                data(event).map(|d: MessageData| PlayerEvent::Error { message: d.message })
+            }
+        };
+
+        decoded.unwrap_or_else(|e| PlayerEvent::Error {
+            message: format!("invalid {:?} event from node: {}", event.event_type, e),
+        })
+    }
+}
+
+fn data<T: DeserializeOwned>(event: &Event) -> Result<T, serde_json::Error> {
+    serde_json::from_value(event.data.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::num::NonZero;
+
+    #[test]
+    fn all_and_none() {
+        assert!(EventFilter::all().contains(PlayerEventKind::PositionUpdate));
+        assert!(EventFilter::default().contains(PlayerEventKind::TrackStart));
+        assert!(!EventFilter::none().contains(PlayerEventKind::TrackStart));
+    }
+
+    #[test]
+    fn only_added_kinds_are_contained() {
+        let filter = EventFilter::none()
+            .with(PlayerEventKind::TrackStart)
+            .with(PlayerEventKind::TrackEnd);
+        assert!(filter.contains(PlayerEventKind::TrackStart));
+        assert!(filter.contains(PlayerEventKind::TrackEnd));
+        assert!(!filter.contains(PlayerEventKind::PositionUpdate));
+        assert!(!filter.contains(PlayerEventKind::AutoDisconnect));
+    }
+
+    #[test]
+    fn built_from_kinds() {
+        let single = EventFilter::from(PlayerEventKind::Error);
+        assert!(single.contains(PlayerEventKind::Error));
+        assert!(!single.contains(PlayerEventKind::TrackStart));
+
+        let collected: EventFilter = [PlayerEventKind::VoiceClosed, PlayerEventKind::AutoDisconnect]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            collected,
+            EventFilter::none()
+                .with(PlayerEventKind::VoiceClosed)
+                .with(PlayerEventKind::AutoDisconnect)
+        );
+        assert_eq!(std::iter::empty().collect::<EventFilter>(), EventFilter::none());
+    }
+
+    #[test]
+    fn decoded_events_match_their_kind() {
+        let cases = [
+            (EventType::TrackStart, json!({}), PlayerEventKind::TrackStart),
+            (EventType::TrackEnd, json!({ "reason": "replaced" }), PlayerEventKind::TrackEnd),
+            (EventType::PositionUpdate, json!({ "position": 1500 }), PlayerEventKind::PositionUpdate),
+            (EventType::ErrorOccurred, json!({ "message": "boom" }), PlayerEventKind::Error),
+            // A payload that does not match the protocol is reported as an error.
+            (EventType::PositionUpdate, json!({}), PlayerEventKind::Error),
+        ];
+        for (event_type, data, kind) in cases {
+            let event = Event { guild_id: NonZero::new(1).unwrap(), event_type, timestamp: 0, data };
+            assert_eq!(PlayerEvent::decode(&event).kind(), kind, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn decodes_track_end_reason() {
+        let event = Event {
+            guild_id: NonZero::new(1).unwrap(),
+            event_type: EventType::TrackEnd,
+            timestamp: 0,
+            data: json!({ "reason": "load_failed" }),
+        };
+        match PlayerEvent::decode(&event) {
+            PlayerEvent::TrackEnd { track: None, reason: TrackEndReason::LoadFailed } => {}
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
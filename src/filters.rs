@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+
+pub const EQUALIZER_BANDS: u8 = 15;
+
+/// Audio filters applied to a player. Unset filters are left disabled.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Filters {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub equalizer: Vec<EqualizerBand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timescale: Option<Timescale>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub karaoke: Option<Karaoke>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tremolo: Option<Tremolo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vibrato: Option<Vibrato>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_pass: Option<LowPass>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_mix: Option<ChannelMix>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EqualizerBand {
+    /// Band index, 0 to 14.
+    pub band: u8,
+    /// Gain multiplier, -0.25 (muted) to 1.0 (doubled).
+    pub gain: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Timescale {
+    pub speed: f64,
+    pub pitch: f64,
+    pub rate: f64,
+}
+
+impl Default for Timescale {
+    fn default() -> Self {
+        Timescale { speed: 1.0, pitch: 1.0, rate: 1.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Karaoke {
+    pub level: f32,
+    pub mono_level: f32,
+    pub filter_band: f32,
+    pub filter_width: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tremolo {
+    pub frequency: f32,
+    pub depth: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vibrato {
+    pub frequency: f32,
+    pub depth: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rotation {
+    pub rotation_hz: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LowPass {
+    /// Values above 1.0 enable the filter.
+    pub smoothing: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelMix {
+    pub left_to_left: f32,
+    pub left_to_right: f32,
+    pub right_to_left: f32,
+    pub right_to_right: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterPreset {
+    BassBoost,
+    Nightcore,
+    Vaporwave,
+    EightD,
+    Karaoke,
+}
+
+impl FilterPreset {
+    pub fn filters(&self) -> Filters {
+        match self {
+            FilterPreset::BassBoost => Filters {
+                equalizer: [0.2, 0.15, 0.1, 0.05, 0.0]
+                    .iter()
+                    .enumerate()
+                    .map(|(band, gain)| EqualizerBand { band: band as u8, gain: *gain })
+                    .collect(),
+                ..Filters::default()
+            },
+            FilterPreset::Nightcore => Filters {
+                timescale: Some(Timescale { speed: 1.2, pitch: 1.2, rate: 1.0 }),
+                ..Filters::default()
+            },
+            FilterPreset::Vaporwave => Filters {
+                timescale: Some(Timescale { speed: 0.85, pitch: 0.8, rate: 1.0 }),
+                tremolo: Some(Tremolo { frequency: 14.0, depth: 0.3 }),
+                ..Filters::default()
+            },
+            FilterPreset::EightD => Filters {
+                rotation: Some(Rotation { rotation_hz: 0.2 }),
+                ..Filters::default()
+            },
+            FilterPreset::Karaoke => Filters {
+                karaoke: Some(Karaoke {
+                    level: 1.0,
+                    mono_level: 1.0,
+                    filter_band: 220.0,
+                    filter_width: 100.0,
+                }),
+                ..Filters::default()
+            },
+        }
+    }
+}
+
+impl Filters {
+    /// Checks every value against the ranges the node accepts, returning the
+    /// first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = 0u16;
+        for band in &self.equalizer {
+            if band.band >= EQUALIZER_BANDS {
+                return Err(format!("equalizer band {} does not exist", band.band));
+            }
+            if seen & (1 << band.band) != 0 {
+                return Err(format!("equalizer band {} is set more than once", band.band));
+            }
+            seen |= 1 << band.band;
+            if !(-0.25..=1.0).contains(&band.gain) {
+                return Err(format!("equalizer gain {} must be between -0.25 and 1.0", band.gain));
+            }
+        }
+        if let Some(timescale) = &self.timescale {
+            for (name, value) in [
+                ("speed", timescale.speed),
+                ("pitch", timescale.pitch),
+                ("rate", timescale.rate),
+            ] {
+                if !value.is_finite() || value <= 0.0 {
+                    return Err(format!("timescale {} must be greater than 0", name));
+                }
+            }
+        }
+        if let Some(karaoke) = &self.karaoke {
+            let values = [karaoke.level, karaoke.mono_level, karaoke.filter_band, karaoke.filter_width];
+            if values.iter().any(|v| !v.is_finite() || *v < 0.0) {
+                return Err("karaoke values must be non-negative".to_string());
+            }
+        }
+        if let Some(tremolo) = &self.tremolo {
+            if !(tremolo.frequency.is_finite() && tremolo.frequency > 0.0)
+                || !(tremolo.depth > 0.0 && tremolo.depth <= 1.0)
+            {
+                return Err("tremolo needs a positive frequency and a depth in (0, 1]".to_string());
+            }
+        }
+        if let Some(vibrato) = &self.vibrato {
+            if !(vibrato.frequency > 0.0 && vibrato.frequency <= 14.0)
+                || !(vibrato.depth > 0.0 && vibrato.depth <= 1.0)
+            {
+                return Err("vibrato needs a frequency in (0, 14] and a depth in (0, 1]".to_string());
+            }
+        }
+        if let Some(rotation) = &self.rotation {
+            if !rotation.rotation_hz.is_finite() {
+                return Err("rotation speed must be finite".to_string());
+            }
+        }
+        if let Some(low_pass) = &self.low_pass {
+            if !low_pass.smoothing.is_finite() || low_pass.smoothing < 0.0 {
+                return Err("low pass smoothing must be non-negative".to_string());
+            }
+        }
+        if let Some(mix) = &self.channel_mix {
+            let values = [mix.left_to_left, mix.left_to_right, mix.right_to_left, mix.right_to_right];
+            if values.iter().any(|v| !(0.0..=1.0).contains(v)) {
+                return Err("channel mix factors must be between 0 and 1".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// How fast the track position advances relative to real time.
+    pub fn playback_speed(&self) -> f64 {
+        self.timescale.map_or(1.0, |t| t.speed * t.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        for preset in [
+            FilterPreset::BassBoost,
+            FilterPreset::Nightcore,
+            FilterPreset::Vaporwave,
+            FilterPreset::EightD,
+            FilterPreset::Karaoke,
+        ] {
+            assert_eq!(preset.filters().validate(), Ok(()), "{:?}", preset);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_equalizer() {
+        let band = |band, gain| Filters { equalizer: vec![EqualizerBand { band, gain }], ..Filters::default() };
+        assert!(band(EQUALIZER_BANDS, 0.0).validate().is_err());
+        assert!(band(0, -0.5).validate().is_err());
+        assert!(band(0, 1.5).validate().is_err());
+        assert!(band(0, f32::NAN).validate().is_err());
+        assert!(band(14, 1.0).validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_duplicate_equalizer_bands() {
+        let filters = Filters {
+            equalizer: vec![EqualizerBand { band: 3, gain: 0.1 }, EqualizerBand { band: 3, gain: 0.2 }],
+            ..Filters::default()
+        };
+        assert!(filters.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_nan_values() {
+        let timescale = Filters {
+            timescale: Some(Timescale { speed: f64::NAN, ..Timescale::default() }),
+            ..Filters::default()
+        };
+        let tremolo = Filters {
+            tremolo: Some(Tremolo { frequency: f32::NAN, depth: 0.5 }),
+            ..Filters::default()
+        };
+        let vibrato = Filters {
+            vibrato: Some(Vibrato { frequency: 2.0, depth: f32::NAN }),
+            ..Filters::default()
+        };
+        let channel_mix = Filters {
+            channel_mix: Some(ChannelMix {
+                left_to_left: f32::NAN,
+                left_to_right: 0.0,
+                right_to_left: 0.0,
+                right_to_right: 1.0,
+            }),
+            ..Filters::default()
+        };
+        for filters in [timescale, tremolo, vibrato, channel_mix] {
+            assert!(filters.validate().is_err(), "{:?}", filters);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_modulation() {
+        let tremolo = |frequency, depth| Filters {
+            tremolo: Some(Tremolo { frequency, depth }),
+            ..Filters::default()
+        };
+        assert!(tremolo(0.0, 0.5).validate().is_err());
+        assert!(tremolo(f32::INFINITY, 0.5).validate().is_err());
+        assert!(tremolo(2.0, 0.0).validate().is_err());
+        assert!(tremolo(2.0, 1.0).validate().is_ok());
+
+        let vibrato = Filters {
+            vibrato: Some(Vibrato { frequency: 15.0, depth: 0.5 }),
+            ..Filters::default()
+        };
+        assert!(vibrato.validate().is_err());
+    }
+
+    #[test]
+    fn playback_speed_combines_speed_and_rate() {
+        let filters = Filters {
+            timescale: Some(Timescale { speed: 1.5, pitch: 1.0, rate: 2.0 }),
+            ..Filters::default()
+        };
+        assert_eq!(filters.playback_speed(), 3.0);
+        assert_eq!(Filters::default().playback_speed(), 1.0);
+    }
+}
@@ -1,45 +1,137 @@
-use log::error;
-use crate::background::processor::RavalinkIPC;
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::warn;
+use std::sync::Mutex;
+use crate::events::{EventFilter, PlayerEvent};
+use crate::handlers::stream::EventSubscription;
+use crate::models::IdleReason;
 use crate::PlayerObject;
-use ravalink_interconnect::protocol::{Event, EventType, Message};
 
 pub trait RavalinkEventHandler {
-    fn handle_event(&self, event: Event);
-    fn handle_error(&self, error: Event);
+    /// The events this handler is woken for. Defaults to all of them.
+    fn filter(&self) -> EventFilter {
+        EventFilter::all()
+    }
+    fn handle_event(&self, event: PlayerEvent);
+    fn handle_error(&self, message: String);
+    /// Called just before the player leaves its voice channel because of its
+    /// idle policy.
+    fn handle_auto_disconnect(&self, _reason: IdleReason) {}
+    /// Called when the handler fell behind and `lost` events were dropped.
+    fn handle_lag(&self, lost: u64) {
+        warn!("Event handler fell behind, {} events were dropped", lost);
+    }
+}
+
+#[async_trait]
+pub trait AsyncRavalinkEventHandler: Send + Sync {
+    /// The events this handler is woken for. Defaults to all of them.
+    fn filter(&self) -> EventFilter {
+        EventFilter::all()
+    }
+    async fn handle_event(&self, event: PlayerEvent);
+    async fn handle_error(&self, message: String);
+    async fn handle_auto_disconnect(&self, _reason: IdleReason) {}
+    async fn handle_lag(&self, lost: u64) {
+        warn!("Event handler fell behind, {} events were dropped", lost);
+    }
+}
+
+/// Runs a [`RavalinkEventHandler`] as an [`AsyncRavalinkEventHandler`]. The
+/// lock only makes a `Send` handler shareable; events arrive one at a time.
+struct SyncHandler<H>(Mutex<H>);
+
+#[async_trait]
+impl<H: RavalinkEventHandler + Send> AsyncRavalinkEventHandler for SyncHandler<H> {
+    fn filter(&self) -> EventFilter {
+        self.0.lock().unwrap().filter()
+    }
+
+    async fn handle_event(&self, event: PlayerEvent) {
+        self.0.lock().unwrap().handle_event(event)
+    }
+
+    async fn handle_error(&self, message: String) {
+        self.0.lock().unwrap().handle_error(message)
+    }
+
+    async fn handle_auto_disconnect(&self, reason: IdleReason) {
+        self.0.lock().unwrap().handle_auto_disconnect(reason)
+    }
+
+    async fn handle_lag(&self, lost: u64) {
+        self.0.lock().unwrap().handle_lag(lost)
+    }
 }
 
 impl PlayerObject {
+    /// Runs `event_handler` for this player's events under `name`, replacing
+    /// any handler already registered with that name.
     pub async fn register_event_handler(
-        &mut self,
+        &self,
+        name: impl Into<String>,
         event_handler: impl RavalinkEventHandler + Send + 'static,
-    ) {
-        let mut t_rx = self.tx.subscribe();
-        let guild_id = self.guild_id.clone();
-
-        tokio::spawn(async move {
-            while let Ok(RavalinkIPC::Message(ravalink_message)) = t_rx.recv().await {
-                let message = ravalink_message.message;
-
-                match message {
-                    Message::Event(event) => {
-                        if let Some(g_id) = ravalink_message.guild_id {
-                            if g_id == guild_id {
-                                match event.event_type {
-                                    EventType::ErrorOccurred => {
-                                        event_handler.handle_error(event);
-                                    }
-                                    _ => {
-                                        event_handler.handle_event(event);
-                                    }
-                                }
-                            }
-                        }
+    ) -> EventSubscription {
+        self.register_async_event_handler(name, SyncHandler(Mutex::new(event_handler))).await
+    }
+
+    /// Like `register_event_handler`, but each event is awaited before the
+    /// next one is delivered.
+    pub async fn register_async_event_handler(
+        &self,
+        name: impl Into<String>,
+        event_handler: impl AsyncRavalinkEventHandler + 'static,
+    ) -> EventSubscription {
+        let mut events = Box::pin(self.subscribe(event_handler.filter()));
+
+        let task = tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    PlayerEvent::Error { message } => event_handler.handle_error(message).await,
+                    PlayerEvent::AutoDisconnect { reason } => {
+                        event_handler.handle_auto_disconnect(reason).await
                     }
-                    _ => {}
+                    PlayerEvent::Lagged { lost } => event_handler.handle_lag(lost).await,
+                    event => event_handler.handle_event(event).await,
                 }
             }
-
-            error!("Receiver closed, no more messages will be processed.");
         });
+
+        self.track_event_handler(name.into(), task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{emit, FakeNode};
+    use ravalink_interconnect::protocol::EventType;
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc;
+
+    struct Recorder(mpsc::UnboundedSender<String>);
+
+    impl RavalinkEventHandler for Recorder {
+        fn handle_event(&self, event: PlayerEvent) {
+            let _ = self.0.send(format!("{:?}", event.kind()));
+        }
+
+        fn handle_error(&self, message: String) {
+            let _ = self.0.send(message);
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_handlers_receive_events_in_order() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let _subscription = player.register_event_handler("recorder", Recorder(tx)).await;
+
+        emit(&player, EventType::TrackStart, json!({}));
+        emit(&player, EventType::ErrorOccurred, json!({ "message": "boom" }));
+        assert_eq!(rx.recv().await.as_deref(), Some("TrackStart"));
+        assert_eq!(rx.recv().await.as_deref(), Some("boom"));
+        assert_eq!(player.event_handlers(), ["recorder"]);
     }
 }
@@ -1 +1,26 @@
-pub mod default;
\ No newline at end of file
+pub mod default;
+pub mod node;
+pub mod stream;
+
+/// Cleans up after a registered handler when dropped, unless detached first.
+pub(crate) struct HandlerGuard {
+    cleanup: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+
+impl HandlerGuard {
+    pub(crate) fn new(cleanup: impl FnOnce() + Send + Sync + 'static) -> Self {
+        HandlerGuard { cleanup: Some(Box::new(cleanup)) }
+    }
+
+    pub(crate) fn detach(&mut self) {
+        self.cleanup = None;
+    }
+}
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        if let Some(cleanup) = self.cleanup.take() {
+            cleanup();
+        }
+    }
+}
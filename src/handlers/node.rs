@@ -0,0 +1,145 @@
+use crate::background::processor::RavalinkIPC;
+use crate::handlers::HandlerGuard;
+use crate::Ravalink;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use log::warn;
+use ravalink_interconnect::protocol::Message;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+/// Handles messages the node sends to the whole client rather than to one
+/// guild, such as stats, shutdown notices and pongs.
+#[async_trait]
+pub trait NodeEventHandler: Send + Sync {
+    async fn handle_message(&self, message: Message);
+    async fn handle_lag(&self, lost: u64) {
+        warn!("Node handler fell behind, {} messages were dropped", lost);
+    }
+}
+
+/// An item of a node message stream.
+#[derive(Clone, Debug)]
+pub enum NodeMessage {
+    Message(Message),
+    /// The stream fell behind and this many messages were dropped.
+    Lagged { lost: u64 },
+}
+
+/// A registered node handler. Dropping it stops the handler; call
+/// [`NodeSubscription::detach`] to keep it running.
+#[must_use = "the handler is stopped when the subscription is dropped"]
+pub struct NodeSubscription {
+    guard: HandlerGuard,
+}
+
+impl NodeSubscription {
+    pub fn detach(mut self) {
+        self.guard.detach();
+    }
+}
+
+fn receive<T: Clone + Send + 'static>(
+    rx: Receiver<T>,
+    message: impl Fn(T) -> Option<Message> + Send + 'static,
+) -> impl Stream<Item = NodeMessage> + Send + 'static {
+    futures::stream::unfold((rx, message), |(mut rx, message)| async move {
+        loop {
+            let item = match rx.recv().await {
+                Ok(received) => match message(received) {
+                    Some(m) => NodeMessage::Message(m),
+                    None => continue,
+                },
+                Err(RecvError::Lagged(lost)) => NodeMessage::Lagged { lost },
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((item, (rx, message)));
+        }
+    })
+}
+
+fn node_message(ipc: RavalinkIPC) -> Option<Message> {
+    match ipc {
+        RavalinkIPC::Message(m) if m.response_tx.is_none() && m.guild_id.is_none() => Some(m.message),
+        _ => None,
+    }
+}
+
+impl Ravalink {
+    /// Returns a stream of the node messages that belong to no guild, with a
+    /// [`NodeMessage::Lagged`] item wherever messages were dropped.
+    pub fn node_messages(&self) -> impl Stream<Item = NodeMessage> + Send + 'static {
+        receive(self.tx.subscribe(), node_message)
+    }
+
+    /// Returns a stream of every message the node sends, for every guild.
+    /// Meant for logging; players still receive their own messages.
+    pub fn all_messages(&self) -> impl Stream<Item = NodeMessage> + Send + 'static {
+        receive(self.firehose.subscribe(), Some)
+    }
+
+    /// Runs `handler` for every node message that belongs to no guild.
+    pub fn register_node_handler(&self, handler: impl NodeEventHandler + 'static) -> NodeSubscription {
+        let mut messages = Box::pin(self.node_messages());
+
+        let task = tokio::spawn(async move {
+            while let Some(item) = messages.next().await {
+                match item {
+                    NodeMessage::Message(message) => handler.handle_message(message).await,
+                    NodeMessage::Lagged { lost } => handler.handle_lag(lost).await,
+                }
+            }
+        });
+
+        let abort = task.abort_handle();
+        NodeSubscription { guard: HandlerGuard::new(move || abort.abort()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ravalink_interconnect::protocol::{Event, EventType};
+    use serde_json::Value;
+    use std::num::NonZero;
+    use tokio::sync::broadcast;
+
+    fn event(guild_id: u64) -> Message {
+        Message::Event(Event {
+            guild_id: NonZero::new(guild_id).unwrap(),
+            event_type: EventType::TrackStart,
+            timestamp: 0,
+            data: Value::Null,
+        })
+    }
+
+    #[tokio::test]
+    async fn dropped_messages_are_reported_in_the_stream() {
+        let (tx, rx) = broadcast::channel(2);
+        for guild_id in 1..=3 {
+            tx.send(event(guild_id)).unwrap();
+        }
+        drop(tx);
+
+        let items: Vec<_> = receive(rx, Some).collect().await;
+        assert!(matches!(
+            &items[..],
+            [NodeMessage::Lagged { lost: 1 }, NodeMessage::Message(_), NodeMessage::Message(_)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn filtered_messages_are_skipped() {
+        let (tx, rx) = broadcast::channel(4);
+        for guild_id in 1..=3 {
+            tx.send(event(guild_id)).unwrap();
+        }
+        drop(tx);
+
+        let odd = |message: Message| {
+            let keep = matches!(&message, Message::Event(event) if event.guild_id.get() % 2 == 1);
+            keep.then_some(message)
+        };
+        assert_eq!(receive(rx, odd).count().await, 2);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::events::{EventFilter, PlayerEvent};
+use crate::handlers::HandlerGuard;
+use crate::PlayerObject;
+use futures::Stream;
+use log::debug;
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Events buffered per subscription before new ones are dropped.
+const SUBSCRIPTION_BUFFER: usize = 64;
+
+struct Subscriber {
+    filter: EventFilter,
+    tx: mpsc::Sender<PlayerEvent>,
+    /// Events dropped since the subscriber was last told about a lag.
+    lost: u64,
+}
+
+impl Subscriber {
+    fn deliver(&mut self, event: &PlayerEvent) {
+        if self.lost > 0 {
+            if self.tx.try_send(PlayerEvent::Lagged { lost: self.lost }).is_err() {
+                self.lost += 1;
+                return;
+            }
+            self.lost = 0;
+        }
+        if self.tx.try_send(event.clone()).is_err() {
+            self.lost += 1;
+        }
+    }
+}
+
+/// The event subscriptions of one player. Events are decoded once by the
+/// player's tracker and only delivered to subscriptions whose filter matches.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    subscribers: Mutex<Vec<Subscriber>>,
+    handlers: Mutex<HashMap<String, (u64, AbortHandle)>>,
+    next_handler_id: AtomicU64,
+}
+
+impl Subscribers {
+    fn add(&self, filter: EventFilter) -> mpsc::Receiver<PlayerEvent> {
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        self.subscribers.lock().unwrap().push(Subscriber { filter, tx, lost: 0 });
+        rx
+    }
+
+    pub(crate) fn publish(&self, guild_id: NonZero<u64>, event: PlayerEvent) {
+        let kind = event.kind();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.tx.is_closed());
+        for subscriber in subscribers.iter_mut().filter(|s| s.filter.contains(kind)) {
+            subscriber.deliver(&event);
+            if subscriber.lost > 0 {
+                debug!("Event subscription for guild {} is full, dropped {:?}", guild_id, kind);
+            }
+        }
+    }
+
+    /// Ends every subscription and stops every handler.
+    pub(crate) fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+        for (_, (_, handler)) in self.handlers.lock().unwrap().drain() {
+            handler.abort();
+        }
+    }
+
+    /// Tracks a handler task under `name`, stopping any handler that had it.
+    fn register(self: &Arc<Self>, name: String, task: JoinHandle<()>) -> EventSubscription {
+        let id = self.next_handler_id.fetch_add(1, Ordering::Relaxed);
+        if let Some((_, previous)) = self.handlers.lock().unwrap().insert(name.clone(), (id, task.abort_handle())) {
+            previous.abort();
+        }
+        let subscribers = Arc::downgrade(self);
+        let registered = name.clone();
+        EventSubscription {
+            name,
+            guard: HandlerGuard::new(move || {
+                if let Some(subscribers) = subscribers.upgrade() {
+                    subscribers.unregister(&registered, Some(id));
+                }
+            }),
+        }
+    }
+
+    fn unregister(&self, name: &str, id: Option<u64>) -> bool {
+        let mut handlers = self.handlers.lock().unwrap();
+        match handlers.get(name) {
+            Some((handler_id, _)) if id.map_or(true, |id| id == *handler_id) => {
+                let (_, handler) = handlers.remove(name).unwrap();
+                handler.abort();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A registered event handler. Dropping it unregisters the handler; call
+/// [`EventSubscription::detach`] to keep it running for the player's lifetime.
+#[must_use = "the handler is unregistered when the subscription is dropped"]
+pub struct EventSubscription {
+    name: String,
+    guard: HandlerGuard,
+}
+
+impl EventSubscription {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Keeps the handler running until the player is removed or
+    /// `unregister_event_handler` is called with its name.
+    pub fn detach(mut self) {
+        self.guard.detach();
+    }
+}
+
+impl PlayerObject {
+    /// Returns a stream of all of this player's events. The stream ends when
+    /// the player is removed.
+    pub fn events(&self) -> impl Stream<Item = PlayerEvent> + Send + 'static {
+        self.subscribe(EventFilter::all())
+    }
+
+    /// Returns a stream of the events that match `filter`. Other events are
+    /// never delivered to it.
+    pub fn subscribe(&self, filter: impl Into<EventFilter>) -> impl Stream<Item = PlayerEvent> + Send + 'static {
+        let rx = self.inner.subscribers.add(filter.into());
+        futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|event| (event, rx))
+        })
+    }
+
+    /// Stops the handler registered under `name`. Returns whether there was one.
+    pub fn unregister_event_handler(&self, name: &str) -> bool {
+        self.inner.subscribers.unregister(name, None)
+    }
+
+    /// Returns the names of the registered event handlers.
+    pub fn event_handlers(&self) -> Vec<String> {
+        self.inner.subscribers.handlers.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub(crate) fn track_event_handler(&self, name: String, task: JoinHandle<()>) -> EventSubscription {
+        self.inner.subscribers.register(name, task)
+    }
+}
@@ -1,27 +1,56 @@
 
 use crate::background::processor::{init_processor, RavalinkIPC};
 use lazy_static::lazy_static;
-use log::error;
-use ravalink_interconnect::protocol::{Command, Message, Request};
+use log::{error, warn};
+use ravalink_interconnect::protocol::{Command, Message, Request, Response};
 use rdkafka::producer::FutureProducer;
-use snafu::Snafu;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, VecDeque};
 use std::num::NonZero;
-use std::sync::Arc;
-use tokio::sync::broadcast::error::SendError;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::{Receiver, Sender};
-use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::sync::{broadcast, watch, Mutex, Notify, RwLock};
+use tokio::time::timeout;
 use nanoid::nanoid;
-use crate::helpers::get_timestamp;
+use crate::helpers::get_unix_timestamp;
 use snafu::ResultExt;
 pub mod managers;
 pub mod background;
+pub mod batch;
 pub mod handlers;
+pub mod errors;
+pub mod events;
+pub mod filters;
+pub mod middleware;
+pub mod models;
+pub mod persistence;
+pub mod rate_limit;
+pub mod state;
 
+mod coalesce;
 mod helpers;
+#[cfg(test)]
+mod test_support;
 pub mod serenity;
 
 use crate::background::connector::{initialize_client, initialize_producer};
+use crate::background::registry::Registry;
+use crate::background::tracker::spawn_player_tracker;
+use crate::coalesce::Coalescer;
+use crate::handlers::stream::Subscribers;
+use crate::managers::fade_manager::FadeState;
+use crate::managers::idle_manager::IdleState;
+use crate::managers::voice_manager::VoiceConnection;
+use crate::middleware::{CommandContext, CommandMiddleware, CommandOrigin, MiddlewareChain};
+use crate::persistence::{open_session, restore_players, Session, StateStore};
+use crate::state::PlayerState;
+use crate::models::{CommandKind, QueuedTrack};
+pub use crate::errors::PlayerError;
+use crate::errors::{
+    DeliveryFailedSnafu, InvalidResponseSnafu, NotConnectedSnafu, ShutdownSnafu, TimeoutSnafu,
+};
 use rdkafka::consumer::StreamConsumer;
 
 lazy_static! {
@@ -31,86 +60,333 @@ lazy_static! {
     pub(crate) static ref RX: Mutex<Option<Receiver<RavalinkIPC>>> = Mutex::new(None);
 }
 
-#[derive(Debug, Snafu)]
-pub enum PlayerError {
-    InitializationError,
-    FailedToReceiveIPCResponse,
-    FailedToSendIPCRequest { source: SendError<RavalinkIPC> },
-}
+pub(crate) const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// The player of one guild. Clones are cheap and share the same player.
+#[derive(Clone)]
 pub struct PlayerObject {
+    inner: Arc<PlayerInner>,
+    origin: CommandOrigin,
+}
+
+/// The state shared by every handle to a player.
+pub(crate) struct PlayerInner {
     guild_id: NonZero<u64>,
     tx: Arc<Sender<RavalinkIPC>>,
     bg_com_tx: Sender<RavalinkIPC>,
+    state: watch::Sender<PlayerState>,
+    queue: Mutex<VecDeque<QueuedTrack>>,
+    fades: Mutex<FadeState>,
+    voice: Mutex<VoiceConnection>,
+    idle: Mutex<IdleState>,
+    shutdown: Arc<Notify>,
+    subscribers: Arc<Subscribers>,
+    middleware: MiddlewareChain,
+    coalescer: Coalescer,
+    /// The client's players, so a destroyed player can remove itself.
+    registry: Weak<Registry>,
+    /// Where the player is saved, if the client persists players.
+    session: Option<Session>,
 }
 
+/// A player that is not kept alive by its holder. Background tasks hold one
+/// so that the player is dropped once every handle to it is.
+pub(crate) struct WeakPlayerObject(Weak<PlayerInner>);
+
+impl WeakPlayerObject {
+    pub(crate) fn upgrade(&self) -> Option<PlayerObject> {
+        let inner = self.0.upgrade()?;
+        Some(PlayerObject { inner, origin: CommandOrigin::User })
+    }
+}
+
+/// A handle to the player of one guild. It holds no lock on `Ravalink`, so it
+/// can be cloned into command contexts and used from any task.
+pub type PlayerHandle = PlayerObject;
+
+const _: fn() = || {
+    fn assert_handle<T: Clone + Send + Sync + 'static>() {}
+    assert_handle::<PlayerHandle>();
+};
+
 impl PlayerObject {
-    pub async fn new(guild_id: NonZero<u64>, com_tx: Sender<RavalinkIPC>) -> Result<Self, PlayerError> {
+    pub(crate) async fn new(
+        guild_id: NonZero<u64>,
+        com_tx: Sender<RavalinkIPC>,
+        middleware: MiddlewareChain,
+        session: Option<Session>,
+        registry: Weak<Registry>,
+    ) -> Result<Self, PlayerError> {
         let (tx, _rx) = broadcast::channel(16);
+        let (state, _) = watch::channel(PlayerState::default());
 
         let handler = PlayerObject {
-            guild_id,
-            tx: Arc::new(tx),
-            bg_com_tx: com_tx,
+            inner: Arc::new(PlayerInner {
+                guild_id,
+                tx: Arc::new(tx),
+                bg_com_tx: com_tx,
+                state,
+                queue: Mutex::new(VecDeque::new()),
+                fades: Mutex::new(FadeState::default()),
+                voice: Mutex::new(VoiceConnection::default()),
+                idle: Mutex::new(IdleState::default()),
+                shutdown: Arc::new(Notify::new()),
+                subscribers: Arc::new(Subscribers::default()),
+                middleware,
+                coalescer: Coalescer::default(),
+                registry,
+                session,
+            }),
+            origin: CommandOrigin::User,
         };
 
+        spawn_player_tracker(&handler);
+
         Ok(handler)
     }
 
+    pub fn guild_id(&self) -> NonZero<u64> {
+        self.inner.guild_id
+    }
+
+    /// Returns a snapshot of the last known player state.
+    pub fn state(&self) -> PlayerState {
+        self.inner.state.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified every time the player state changes.
+    pub fn watch_state(&self) -> watch::Receiver<PlayerState> {
+        self.inner.state.subscribe()
+    }
+
+    /// Returns the current playback position, interpolated from the last
+    /// position the node reported.
+    pub fn position(&self) -> Duration {
+        self.inner.state.borrow().interpolated_position(get_unix_timestamp())
+    }
+
+    /// Returns the node clock minus the bot clock, in milliseconds.
+    pub fn clock_skew(&self) -> i64 {
+        self.inner.state.borrow().clock_skew
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakPlayerObject {
+        WeakPlayerObject(Arc::downgrade(&self.inner))
+    }
+
+    /// Returns a clone whose commands are marked as sent by the library
+    /// rather than by the user.
+    pub(crate) fn internal(&self) -> PlayerObject {
+        PlayerObject { origin: CommandOrigin::Internal, ..self.clone() }
+    }
+
     async fn send_request_with_response(
         &self,
         command: Command,
         voice_channel_id: Option<NonZero<u64>>,
-    ) -> Result<Message, PlayerError> {
+    ) -> Result<Response, PlayerError> {
+        if CommandKind::from(&command).coalesces() {
+            return self.send_coalesced(command, voice_channel_id).await;
+        }
+        self.run_command(command, voice_channel_id).await
+    }
+
+    /// Sends `command` through the middleware chain.
+    async fn run_command(
+        &self,
+        command: Command,
+        voice_channel_id: Option<NonZero<u64>>,
+    ) -> Result<Response, PlayerError> {
+        let context = self.before_send(command, voice_channel_id).await?;
+        let result = self.send_command(context.command.clone(), context.voice_channel_id).await;
+        self.after_send(&context, result).await
+    }
+
+    /// Runs every layer's `before` hook, returning the command to send.
+    async fn before_send(
+        &self,
+        command: Command,
+        voice_channel_id: Option<NonZero<u64>>,
+    ) -> Result<CommandContext, PlayerError> {
+        let mut context = CommandContext {
+            guild_id: self.inner.guild_id,
+            voice_channel_id,
+            command,
+            origin: self.origin,
+        };
+        for layer in self.inner.middleware.iter() {
+            layer.before(&mut context).await?;
+        }
+        Ok(context)
+    }
+
+    /// Runs every layer's `after` hook on the result of a sent command.
+    async fn after_send(
+        &self,
+        context: &CommandContext,
+        result: Result<Response, PlayerError>,
+    ) -> Result<Response, PlayerError> {
+        for layer in self.inner.middleware.iter() {
+            layer.after(context, &result).await?;
+        }
+        result
+    }
+
+    async fn send_command(
+        &self,
+        command: Command,
+        voice_channel_id: Option<NonZero<u64>>,
+    ) -> Result<Response, PlayerError> {
         let job_id = nanoid!();
-        let guild_id = self.guild_id.clone();
+        let guild_id = self.inner.guild_id.clone();
+        let kind = CommandKind::from(&command);
+
+        if kind.requires_connection() && self.inner.state.borrow().voice_channel_id.is_none() {
+            return NotConnectedSnafu { guild_id, command: kind }.fail();
+        }
 
-        self.bg_com_tx
+        let sent_at = get_unix_timestamp();
+        let rx = self.inner.tx.subscribe();
+
+        self.inner.bg_com_tx
             .send(RavalinkIPC::create_bot_request(
                 Message::Request(Request {
                     job_id: job_id.clone(),
                     guild_id: guild_id.clone(),
                     voice_channel_id,
-                    command,
-                    timestamp: get_timestamp(),
+                    command: command.clone(),
+                    timestamp: sent_at.as_secs(),
                 }),
-                self.tx.clone(),
-                self.guild_id.clone(),
+                self.inner.tx.clone(),
+                self.inner.guild_id.clone(),
             ))
-            .context(FailedToSendIPCRequestSnafu)?;
+            .context(DeliveryFailedSnafu { guild_id: Some(guild_id), command: kind })?;
         
-        self.wait_for_response(job_id, guild_id).await
+        let response = self.wait_for_response(rx, &job_id, kind).await?;
+        if let Some(reason) = &response.error {
+            return Err(self.rejection(&response, kind, reason.clone()));
+        }
+
+        self.inner.state.send_modify(|s| {
+            let received_at = get_unix_timestamp();
+            s.record_clock_skew(sent_at, received_at, response.timestamp);
+            s.apply_command(&command, voice_channel_id, &response, received_at);
+        });
+
+        Ok(response)
+    }
+
+    fn rejection(&self, response: &Response, command: CommandKind, reason: String) -> PlayerError {
+        let guild_id = self.inner.guild_id;
+        let job_id = response.job_id.clone();
+        match response.data.get("code").and_then(|c| c.as_str()) {
+            Some("unsupported") => PlayerError::Unsupported { guild_id, job_id, command },
+            Some("not_connected") => PlayerError::NotConnected { guild_id, command },
+            _ => PlayerError::Rejected { guild_id, job_id, command, reason },
+        }
     }
 
-    //Probably need to implement a timeout here
-    pub async fn wait_for_response(
+    fn decode_response<T: DeserializeOwned>(
         &self,
-        job_id: String,
-        guild_id: NonZero<u64>,
-    ) -> Result<Message, PlayerError > {
-        let mut rx = self.tx.subscribe();
-        loop {
-            match rx.recv().await {
-                Ok(RavalinkIPC::Message(response)) => {
-                    if let Message::Response(res) = &response.message {
-                        if res.job_id == job_id && res.guild_id == guild_id {
-                            return Ok(response.message.clone());
+        response: Response,
+        command: CommandKind,
+    ) -> Result<T, PlayerError> {
+        serde_json::from_value(response.data).context(InvalidResponseSnafu {
+            guild_id: self.inner.guild_id,
+            job_id: response.job_id,
+            command,
+        })
+    }
+
+    async fn wait_for_response(
+        &self,
+        mut rx: Receiver<RavalinkIPC>,
+        job_id: &str,
+        command: CommandKind,
+    ) -> Result<Response, PlayerError> {
+        let guild_id = self.inner.guild_id;
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(RavalinkIPC::Message(response)) => {
+                        if let Message::Response(res) = response.message {
+                            if res.job_id == job_id && res.guild_id == guild_id {
+                                return Ok(res);
+                            }
                         }
                     }
-                }
-                Err(e) => {
-                    error!("Failed to receive response: {:?}", e);
-                    return Err(PlayerError::FailedToReceiveIPCResponse);
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Response listener for guild {} skipped {} messages", guild_id, skipped);
+                    }
+                    Err(RecvError::Closed) => {
+                        error!("Response channel for guild {} closed", guild_id);
+                        return ShutdownSnafu { guild_id: Some(guild_id), command }.fail();
+                    }
                 }
             }
+        };
+
+        match timeout(RESPONSE_TIMEOUT, wait).await {
+            Ok(result) => result,
+            Err(_) => TimeoutSnafu { guild_id: Some(guild_id), job_id, command }.fail(),
         }
     }
 }
 
+/// The client. Every method takes `&self`, so share it as `Arc<Ravalink>`
+/// without a lock around it.
 pub struct Ravalink {
-    pub players: Arc<RwLock<HashMap<String, PlayerObject>>>,
+    players: Arc<Registry>,
     pub tx: Sender<RavalinkIPC>,
-    pub rx: Receiver<RavalinkIPC>,
+    firehose: Sender<Message>,
+    middleware: MiddlewareChain,
+    session: Option<Session>,
+}
+
+impl Ravalink {
+    /// Returns the player for `guild_id`, creating it if the guild has none.
+    pub async fn get_or_create_player(&self, guild_id: NonZero<u64>) -> Result<PlayerHandle, PlayerError> {
+        if let Some(player) = self.get_player(guild_id).await {
+            return Ok(player);
+        }
+
+        let mut players = self.players.write().await;
+        if let Some(player) = players.get(&guild_id) {
+            return Ok(player.clone());
+        }
+        let player = PlayerObject::new(
+            guild_id,
+            self.tx.clone(),
+            self.middleware.clone(),
+            self.session.clone(),
+            Arc::downgrade(&self.players),
+        ).await?;
+        players.insert(guild_id, player.clone());
+        Ok(player)
+    }
+
+    pub async fn get_player(&self, guild_id: NonZero<u64>) -> Option<PlayerHandle> {
+        self.players.read().await.get(&guild_id).cloned()
+    }
+
+    /// Removes the player from the registry, stops its background work and
+    /// forgets its saved state, so it is not reattached after a restart. The
+    /// node's player is left alone; use `ChannelManager::destroy` to tear it
+    /// down as well.
+    pub async fn remove_player(&self, guild_id: NonZero<u64>) -> Option<PlayerHandle> {
+        let mut players = self.players.write().await;
+        let player = players.remove(&guild_id)?;
+        player.inner.shutdown.notify_one();
+        let _ = self.tx.send(RavalinkIPC::ReleaseGuild(guild_id));
+        drop(players);
+        player.forget().await;
+        Some(player)
+    }
+
+    pub async fn players(&self) -> Vec<PlayerHandle> {
+        self.players.read().await.values().cloned().collect()
+    }
 }
 
 #[derive(Clone)]
@@ -132,10 +408,19 @@ pub struct RavalinkConfig {
     pub ssl: Option<SSLConfig>,
     pub sasl: Option<SASLConfig>,
     pub kafka_topic: String,
+    /// Where players are saved so they can be reattached after a restart.
+    pub state_store: Option<Arc<dyn StateStore>>,
+    /// Session to resume. Defaults to the one saved in `state_store`, or a new one.
+    pub session_id: Option<String>,
+    /// Layers run around every player command, in order.
+    pub middleware: Vec<Arc<dyn CommandMiddleware>>,
 }
 
 
-pub async fn init_ravalink(broker: String, config: RavalinkConfig) -> Arc<Mutex<Ravalink>> {
+/// Connects to the broker and starts the client. Players saved in
+/// `RavalinkConfig::state_store` are reattached in the background and show up
+/// in the registry as they come back.
+pub async fn init_ravalink(broker: String, config: RavalinkConfig) -> Arc<Ravalink> {
     let consumer = initialize_client(&broker, &config).await;
     let producer = initialize_producer(&broker, &config);
 
@@ -152,15 +437,36 @@ pub async fn init_ravalink(broker: String, config: RavalinkConfig) -> Arc<Mutex<
         *rx_lock = Some(tx.subscribe());
     }
 
+    let (firehose, _) = broadcast::channel(64);
     let task_tx = tx.clone();
+    let task_firehose = firehose.clone();
+    let processor_rx = tx.subscribe();
+    let processor_config = config.clone();
 
     tokio::task::spawn(async move {
-        init_processor(task_tx.subscribe(), task_tx, consumer.into(), producer, config).await;
+        init_processor(processor_rx, task_tx, task_firehose, consumer.into(), producer, processor_config).await;
     });
 
-    Arc::new(Mutex::new(Ravalink {
-        players: Arc::new(RwLock::new(HashMap::new())),
+    let players = Arc::new(RwLock::new(HashMap::new()));
+
+    let middleware: MiddlewareChain = Arc::new(config.middleware);
+    let session = match config.state_store {
+        Some(store) => Some(open_session(store, config.session_id).await),
+        None => None,
+    };
+    if let Some(session) = session.clone() {
+        // Reattaching waits on the node, which must not hold up startup.
+        let (players, tx, middleware) = (players.clone(), tx.clone(), middleware.clone());
+        tokio::spawn(async move {
+            restore_players(&session, &players, &tx, &middleware).await;
+        });
+    }
+
+    Arc::new(Ravalink {
+        players,
         tx: tx.clone(),
-        rx: tx.subscribe(),
-    }))
+        firehose,
+        middleware,
+        session,
+    })
 }
\ No newline at end of file
@@ -1,4 +1,10 @@
 pub mod channel_manager;
 pub mod player_manager;
 pub mod track_manager;
+pub mod queue_manager;
+pub mod search_manager;
+pub mod filter_manager;
+pub mod fade_manager;
+pub mod voice_manager;
+pub mod idle_manager;
 pub mod default_manager;
\ No newline at end of file
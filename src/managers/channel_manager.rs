@@ -1,33 +1,116 @@
-use ravalink_interconnect::protocol::{Command, Message};
+use ravalink_interconnect::protocol::Command;
 use std::num::NonZero;
+use crate::errors::NotConnectedSnafu;
+use crate::managers::voice_manager::VoiceConnection;
+use crate::models::{CommandKind, ConnectionInfo};
 use crate::{PlayerError, PlayerObject};
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait ChannelManager {
     async fn connect(
-        &mut self,
+        &self,
         voice_channel_id: NonZero<u64>,
-    ) -> Result<Message, PlayerError>;
-    async fn stop(&self) -> Result<Message, PlayerError>;
+    ) -> Result<ConnectionInfo, PlayerError>;
+    /// Stops the current track. The player stays in its voice channel.
+    async fn stop(&self) -> Result<(), PlayerError>;
+    /// Leaves the voice channel, keeping the player and its queue.
+    async fn disconnect(&self) -> Result<(), PlayerError>;
+    /// Moves to another voice channel without interrupting the current track
+    /// or the queue.
+    async fn move_to(
+        &self,
+        voice_channel_id: NonZero<u64>,
+    ) -> Result<ConnectionInfo, PlayerError>;
+    /// Destroys the player on the node, removes it from `Ravalink::players`
+    /// and releases its routing state in the processor.
+    async fn destroy(&self) -> Result<(), PlayerError>;
 }
 
 #[async_trait]
 impl ChannelManager for PlayerObject {
     async fn connect(
-        &mut self,
+        &self,
         voice_channel_id: NonZero<u64>,
-    ) -> Result<Message, PlayerError> {  
+    ) -> Result<ConnectionInfo, PlayerError> {  
         self.send_request_with_response(
             Command::Connect,
             Some(voice_channel_id),
-        ).await
+        ).await?;
+
+        Ok(ConnectionInfo {
+            guild_id: self.inner.guild_id,
+            voice_channel_id,
+        })
     }
     
-    async fn stop(&self) -> Result<Message, PlayerError> {
+    async fn stop(&self) -> Result<(), PlayerError> {
+        let (fade_out, volume) = {
+            let fades = self.inner.fades.lock().await;
+            (fades.settings.fade_out, fades.volume)
+        };
+        let fading = !fade_out.is_zero() && {
+            let state = self.inner.state.borrow();
+            state.track.is_some() && !state.paused
+        };
+        if fading {
+            self.fade_to(0.0, fade_out).await?;
+        }
+
         self.send_request_with_response(
             Command::Stop,
             None,
-        ).await
+        ).await?;
+
+        if fading {
+            self.internal().send_volume(volume).await?;
+        }
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), PlayerError> {
+        self.send_request_with_response(
+            Command::Disconnect,
+            None,
+        ).await?;
+        *self.inner.voice.lock().await = VoiceConnection::default();
+        Ok(())
+    }
+
+    async fn move_to(
+        &self,
+        voice_channel_id: NonZero<u64>,
+    ) -> Result<ConnectionInfo, PlayerError> {
+        if self.inner.state.borrow().voice_channel_id.is_none() {
+            return NotConnectedSnafu {
+                guild_id: self.inner.guild_id,
+                command: CommandKind::Connect,
+            }.fail();
+        }
+
+        self.send_request_with_response(
+            Command::Connect,
+            Some(voice_channel_id),
+        ).await?;
+
+        Ok(ConnectionInfo {
+            guild_id: self.inner.guild_id,
+            voice_channel_id,
+        })
+    }
+
+    async fn destroy(&self) -> Result<(), PlayerError> {
+        let result = self.send_request_with_response(
+            Command::Destroy,
+            None,
+        ).await;
+
+        self.inner.queue.lock().await.clear();
+        *self.inner.voice.lock().await = VoiceConnection::default();
+        self.inner.shutdown.notify_one();
+        self.forget().await;
+        self.unregister().await?;
+
+        result.map(|_| ())
     }
 }
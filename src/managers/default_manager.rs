@@ -1,48 +1,42 @@
 use std::sync::Arc;
-use std::time::Duration;
 use nanoid::nanoid;
 use ravalink_interconnect::protocol::Message;
 use async_trait::async_trait;
-use tokio::sync::broadcast::error::SendError;
+use snafu::ResultExt;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::time::timeout;
-use crate::RavalinkIPC;
+use crate::errors::{DeliveryFailedSnafu, ShutdownSnafu, TimeoutSnafu};
+use crate::models::CommandKind;
+use crate::{PlayerError, RavalinkIPC, RESPONSE_TIMEOUT};
 use crate::TX;
 
-#[derive(Debug)]
-pub enum DefaultError {
-    InitializationError,
-    FailedToReceiveIPCResponse,
-    FailedToSendIPCRequest { source: SendError<RavalinkIPC> },
-    NoGlobalTX,
-}
-
 //CHECK IF ITS WORKING, implement it in the bot and implement the wait for Pong
 pub struct DefaultObject;
 
 #[async_trait]
 pub trait DefaultManager {
-    async fn ping(&self) -> Result<Message, DefaultError>;
+    async fn ping(&self) -> Result<Message, PlayerError>;
 }
 
 #[async_trait]
 impl DefaultManager for DefaultObject {
-    async fn ping(&self) -> Result<Message, DefaultError> {
+    async fn ping(&self) -> Result<Message, PlayerError> {
 
         let ping_id = nanoid!();
+        let command = CommandKind::Ping;
 
         let tx_lock = TX.lock().await;
         let sender = if let Some(ref tx) = *tx_lock {
             Arc::new(tx.clone())
         } else {
-            return Err(DefaultError::NoGlobalTX);
+            return ShutdownSnafu { guild_id: None, command }.fail();
         };
 
+        let mut rx = sender.subscribe();
         let ping = RavalinkIPC::create_bot_ping_request(Message::Ping { id: ping_id.clone() }, sender.clone());
-        sender.send(ping).map_err(|source| DefaultError::FailedToSendIPCRequest { source })?;
+        sender.send(ping).context(DeliveryFailedSnafu { guild_id: None, command })?;
 
-        let mut rx = sender.subscribe();
-        let timeout_duration = Duration::from_secs(5);
-        match timeout(timeout_duration, async {
+        match timeout(RESPONSE_TIMEOUT, async {
             loop {
                 match rx.recv().await {
   
@@ -54,14 +48,16 @@ impl DefaultManager for DefaultObject {
                         }
                     }
 
-                    Err(_) => {
-                        return Err(DefaultError::FailedToReceiveIPCResponse);
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+
+                    Err(RecvError::Closed) => {
+                        return ShutdownSnafu { guild_id: None, command }.fail();
                     }
                 }
             }
         }).await {
             Ok(result) => result,
-            Err(_) => Err(DefaultError::FailedToReceiveIPCResponse),
+            Err(_) => TimeoutSnafu { guild_id: None, job_id: ping_id.clone(), command }.fail(),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,247 @@
+use async_trait::async_trait;
+use log::{error, warn};
+use ravalink_interconnect::protocol::Command;
+use std::time::Duration;
+use tokio::time::sleep;
+use crate::helpers::get_unix_timestamp;
+use crate::models::{CommandKind, FadeSettings, TrackInfo};
+use crate::{PlayerError, PlayerObject};
+
+const FADE_STEP: Duration = Duration::from_millis(250);
+
+pub(crate) struct FadeState {
+    pub(crate) settings: FadeSettings,
+    /// Bumped whenever a fade starts or the volume is set directly, so older
+    /// fades notice and stop.
+    pub(crate) generation: u64,
+    /// Volume the user asked for, which fade-ins return to.
+    pub(crate) volume: f32,
+    pub(crate) crossfading: bool,
+    /// The track a crossfade out of failed, so it is not retried every poll.
+    pub(crate) failed_crossfade: Option<String>,
+    /// Set once the node turns crossfades down as unsupported.
+    pub(crate) crossfade_unsupported: bool,
+}
+
+impl Default for FadeState {
+    fn default() -> Self {
+        FadeState {
+            settings: FadeSettings::default(),
+            generation: 0,
+            volume: 1.0,
+            crossfading: false,
+            failed_crossfade: None,
+            crossfade_unsupported: false,
+        }
+    }
+}
+
+#[async_trait]
+pub trait FadeManager {
+    /// Ramps the volume to `target` over `duration`. Returns early with the
+    /// volume reached so far if another fade or `set_volume` takes over.
+    async fn fade_volume(&self, target: f32, duration: Duration) -> Result<f32, PlayerError>;
+    async fn set_fade_settings(&self, settings: FadeSettings);
+    async fn fade_settings(&self) -> FadeSettings;
+}
+
+impl PlayerObject {
+    pub(crate) async fn fade_to(&self, target: f32, duration: Duration) -> Result<f32, PlayerError> {
+        let player = self.internal();
+        let generation = {
+            let mut fades = self.inner.fades.lock().await;
+            fades.generation += 1;
+            fades.generation
+        };
+
+        let start = self.inner.state.borrow().volume;
+        let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+        for step in 1..=steps {
+            sleep(duration / steps).await;
+            if self.inner.fades.lock().await.generation != generation {
+                return Ok(self.inner.state.borrow().volume);
+            }
+            let volume = start + (target - start) * step as f32 / steps as f32;
+            player.send_volume(volume).await?;
+        }
+        Ok(target)
+    }
+
+    /// Starts crossfading into the next track in the background once the
+    /// current one is within the crossfade window of its end.
+    pub(crate) async fn crossfade_if_due(&self) {
+        let current = self.inner.state.borrow().track.clone();
+        let crossfade = {
+            let mut fades = self.inner.fades.lock().await;
+            let crossfade = fades.settings.crossfade;
+            let failed = current.as_ref().is_some_and(|track| fades.failed_crossfade.as_ref() == Some(&track.url));
+            if crossfade.is_zero()
+                || fades.crossfading
+                || fades.crossfade_unsupported
+                || failed
+                || !self.crossfade_due(crossfade)
+            {
+                return;
+            }
+            fades.crossfading = true;
+            crossfade
+        };
+
+        let player = self.clone();
+        tokio::spawn(async move {
+            let result = match player.peek_after(current.as_ref()).await {
+                Some(url) => player.crossfade(url, crossfade).await.map(Some),
+                None => Ok(None),
+            };
+            // The next track only leaves the queue once the node has started it.
+            if let Ok(Some(_)) = result {
+                player.next_after(current.clone()).await;
+            }
+
+            let mut fades = player.inner.fades.lock().await;
+            fades.crossfading = false;
+            match result {
+                Err(PlayerError::Unsupported { .. }) => {
+                    warn!("Node does not support crossfades, disabling them for guild {}", player.inner.guild_id);
+                    fades.crossfade_unsupported = true;
+                }
+                Err(e) => {
+                    error!("Failed to crossfade in guild {}: {}", player.inner.guild_id, e);
+                    fades.failed_crossfade = current.map(|track| track.url);
+                }
+                Ok(_) => {}
+            }
+        });
+    }
+
+    fn crossfade_due(&self, crossfade: Duration) -> bool {
+        let state = self.inner.state.borrow();
+        match &state.track {
+            Some(track) if !track.is_stream && !state.paused && !track.duration.is_zero() => {
+                let position = state.interpolated_position(get_unix_timestamp());
+                position < track.duration && track.duration - position <= crossfade
+            }
+            _ => false,
+        }
+    }
+
+    async fn crossfade(&self, url: String, duration: Duration) -> Result<TrackInfo, PlayerError> {
+        let response = self.send_request_with_response(
+            Command::Crossfade { url, duration: duration.as_millis() as u64 },
+            None,
+        ).await?;
+        self.decode_response(response, CommandKind::Crossfade)
+    }
+}
+
+#[async_trait]
+impl FadeManager for PlayerObject {
+    async fn fade_volume(&self, target: f32, duration: Duration) -> Result<f32, PlayerError> {
+        self.validate_volume(target)?;
+
+        self.inner.fades.lock().await.volume = target;
+        self.fade_to(target, duration).await
+    }
+
+    async fn set_fade_settings(&self, settings: FadeSettings) {
+        self.inner.fades.lock().await.settings = settings;
+    }
+
+    async fn fade_settings(&self) -> FadeSettings {
+        self.inner.fades.lock().await.settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::managers::player_manager::Player;
+    use crate::managers::queue_manager::QueueManager;
+    use crate::test_support::{play_reply, track, FakeNode, Reply};
+    use serde_json::json;
+
+    fn crossfade_reply(result: Result<(), &'static str>) -> impl Fn(&Command) -> Reply {
+        move |command| match command {
+            Command::Crossfade { url, .. } => result.map(|()| json!(track(url))),
+            _ => play_reply(command),
+        }
+    }
+
+    /// Leaves `url` five seconds from its end, inside a ten second crossfade.
+    async fn near_the_end(player: &PlayerObject, url: &str) {
+        player.set_fade_settings(FadeSettings { crossfade: Duration::from_secs(10), ..FadeSettings::default() }).await;
+        player.inner.state.send_modify(|s| {
+            s.track = Some(track(url));
+            s.position = Duration::from_secs(175);
+            s.updated_at = get_unix_timestamp();
+        });
+    }
+
+    async fn run_crossfade(player: &PlayerObject) {
+        player.crossfade_if_due().await;
+        while player.inner.fades.lock().await.crossfading {
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn crossfading_takes_the_next_track_off_the_queue() {
+        let node = FakeNode::spawn(crossfade_reply(Ok(())));
+        let player = node.player().await;
+        near_the_end(&player, "a").await;
+        player.enqueue("b".to_string().into()).await.unwrap();
+
+        run_crossfade(&player).await;
+        assert!(player.list().await.is_empty());
+        assert_eq!(player.inner.state.borrow().track.as_ref().map(|t| t.url.as_str()), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_crossfade_keeps_the_track_and_is_not_retried() {
+        let node = FakeNode::spawn(crossfade_reply(Err("boom")));
+        let player = node.player().await;
+        near_the_end(&player, "a").await;
+        player.enqueue("b".to_string().into()).await.unwrap();
+
+        run_crossfade(&player).await;
+        run_crossfade(&player).await;
+        assert_eq!(node.received().len(), 1);
+        assert_eq!(player.list().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn crossfading_stops_once_the_node_reports_it_unsupported() {
+        let node = FakeNode::spawn(crossfade_reply(Err("unsupported")));
+        let player = node.player().await;
+        near_the_end(&player, "a").await;
+        player.enqueue("b".to_string().into()).await.unwrap();
+        run_crossfade(&player).await;
+
+        near_the_end(&player, "c").await;
+        run_crossfade(&player).await;
+        assert_eq!(node.received().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_failed_play_restores_the_volume_it_muted() {
+        let node = FakeNode::spawn(|command| match command {
+            Command::Play { .. } => Err("boom"),
+            _ => Ok(serde_json::Value::Null),
+        });
+        let player = node.player().await;
+        player.set_fade_settings(FadeSettings { fade_in: Duration::from_secs(1), ..FadeSettings::default() }).await;
+
+        assert!(player.play("a".to_string()).await.is_err());
+        let received = node.received();
+        assert!(
+            matches!(
+                &received[..],
+                [Command::SetVolume { volume: muted }, Command::Play { .. }, Command::SetVolume { volume: restored }]
+                    if *muted == 0.0 && *restored == 1.0
+            ),
+            "{:?}",
+            received,
+        );
+        assert_eq!(player.inner.state.borrow().volume, 1.0);
+    }
+}
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use ravalink_interconnect::protocol::Command;
+use crate::errors::InvalidArgumentSnafu;
+use crate::filters::{FilterPreset, Filters};
+use crate::models::CommandKind;
+use crate::{PlayerError, PlayerObject};
+
+#[async_trait]
+pub trait FilterManager {
+    /// Validates `filters` and replaces every active filter with them in one command.
+    async fn set_filters(&self, filters: Filters) -> Result<Filters, PlayerError>;
+    async fn apply_preset(&self, preset: FilterPreset) -> Result<Filters, PlayerError>;
+    async fn clear_filters(&self) -> Result<(), PlayerError>;
+    async fn filters(&self) -> Filters;
+}
+
+#[async_trait]
+impl FilterManager for PlayerObject {
+    async fn set_filters(&self, filters: Filters) -> Result<Filters, PlayerError> {
+        if let Err(reason) = filters.validate() {
+            return InvalidArgumentSnafu {
+                guild_id: self.inner.guild_id,
+                command: CommandKind::SetFilters,
+                reason,
+            }.fail();
+        }
+
+        let payload = serde_json::to_value(&filters).expect("Filters always serialize");
+        self.send_request_with_response(
+            Command::SetFilters { filters: payload },
+            None,
+        ).await?;
+        Ok(filters)
+    }
+
+    async fn apply_preset(&self, preset: FilterPreset) -> Result<Filters, PlayerError> {
+        self.set_filters(preset.filters()).await
+    }
+
+    async fn clear_filters(&self) -> Result<(), PlayerError> {
+        self.set_filters(Filters::default()).await?;
+        Ok(())
+    }
+
+    async fn filters(&self) -> Filters {
+        self.inner.state.borrow().filters.clone()
+    }
+}
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use log::{error, info};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use crate::events::PlayerEvent;
+use crate::managers::channel_manager::ChannelManager;
+use crate::models::{IdlePolicy, IdleReason};
+use crate::PlayerObject;
+
+#[derive(Default)]
+pub(crate) struct IdleState {
+    pub(crate) policy: IdlePolicy,
+    /// Users other than the bot in the player's voice channel, or `None` until
+    /// the bot has reported them.
+    pub(crate) members: Option<HashSet<u64>>,
+    idle_since: Option<Instant>,
+    alone_since: Option<Instant>,
+}
+
+impl IdleState {
+    /// Updates the timers and returns the reason to leave, if one has expired.
+    fn check(&mut self, playing: bool, now: Instant) -> Option<IdleReason> {
+        self.idle_since = if playing { None } else { self.idle_since.or(Some(now)) };
+        let alone = self.members.as_ref().is_some_and(|members| members.is_empty());
+        self.alone_since = if alone { self.alone_since.or(Some(now)) } else { None };
+
+        let expired = |since: Option<Instant>, timeout: Option<Duration>| {
+            matches!((since, timeout), (Some(since), Some(timeout)) if now - since >= timeout)
+        };
+        if expired(self.alone_since, self.policy.alone_timeout) {
+            Some(IdleReason::Alone)
+        } else if expired(self.idle_since, self.policy.idle_timeout) {
+            Some(IdleReason::Idle)
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.idle_since = None;
+        self.alone_since = None;
+    }
+}
+
+#[async_trait]
+pub trait IdleManager {
+    async fn set_idle_policy(&self, policy: IdlePolicy);
+    async fn idle_policy(&self) -> IdlePolicy;
+}
+
+impl PlayerObject {
+    /// Leaves the voice channel in the background once the idle policy says
+    /// so, notifying event handlers first.
+    pub(crate) async fn disconnect_if_idle(&self) {
+        let (connected, playing) = {
+            let state = self.inner.state.borrow();
+            (state.voice_channel_id.is_some(), state.track.is_some() && !state.paused)
+        };
+
+        let reason = {
+            let mut idle = self.inner.idle.lock().await;
+            if !connected {
+                idle.reset();
+                return;
+            }
+            match idle.check(playing, Instant::now()) {
+                Some(reason) => {
+                    idle.reset();
+                    reason
+                }
+                None => return,
+            }
+        };
+
+        info!("Leaving voice in guild {}: {:?}", self.inner.guild_id, reason);
+        self.inner.subscribers.publish(self.inner.guild_id, PlayerEvent::AutoDisconnect { reason });
+
+        // Waiting for the node here would stall the tracker's event loop.
+        let player = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = player.disconnect().await {
+                error!("Failed to auto-disconnect guild {}: {}", player.inner.guild_id, e);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl IdleManager for PlayerObject {
+    async fn set_idle_policy(&self, policy: IdlePolicy) {
+        let mut idle = self.inner.idle.lock().await;
+        idle.policy = policy;
+        idle.reset();
+    }
+
+    async fn idle_policy(&self) -> IdlePolicy {
+        self.inner.idle.lock().await.policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn idle_timer_expires_when_nothing_plays() {
+        let mut idle = IdleState::default();
+        idle.policy.idle_timeout = Some(10 * SECOND);
+        let t0 = Instant::now();
+
+        assert_eq!(idle.check(false, t0), None);
+        assert_eq!(idle.check(false, t0 + 9 * SECOND), None);
+        assert_eq!(idle.check(false, t0 + 10 * SECOND), Some(IdleReason::Idle));
+    }
+
+    #[test]
+    fn playing_restarts_the_idle_timer() {
+        let mut idle = IdleState::default();
+        idle.policy.idle_timeout = Some(10 * SECOND);
+        let t0 = Instant::now();
+
+        idle.check(false, t0);
+        idle.check(true, t0 + 5 * SECOND);
+        assert_eq!(idle.check(false, t0 + 12 * SECOND), None);
+        assert_eq!(idle.check(false, t0 + 22 * SECOND), Some(IdleReason::Idle));
+    }
+
+    #[test]
+    fn alone_timer_waits_for_known_members() {
+        let mut idle = IdleState::default();
+        idle.policy.alone_timeout = Some(5 * SECOND);
+        let t0 = Instant::now();
+
+        idle.check(true, t0);
+        assert_eq!(idle.check(true, t0 + 60 * SECOND), None);
+
+        idle.members = Some(HashSet::new());
+        idle.check(true, t0 + 60 * SECOND);
+        assert_eq!(idle.check(true, t0 + 64 * SECOND), None);
+        assert_eq!(idle.check(true, t0 + 65 * SECOND), Some(IdleReason::Alone));
+    }
+
+    #[test]
+    fn company_stops_the_alone_timer() {
+        let mut idle = IdleState::default();
+        idle.policy.alone_timeout = Some(5 * SECOND);
+        idle.members = Some(HashSet::new());
+        let t0 = Instant::now();
+        idle.check(true, t0);
+
+        idle.members = Some(HashSet::from([42]));
+        assert_eq!(idle.check(true, t0 + 10 * SECOND), None);
+        idle.members = Some(HashSet::new());
+        assert_eq!(idle.check(true, t0 + 11 * SECOND), None);
+        assert_eq!(idle.check(true, t0 + 16 * SECOND), Some(IdleReason::Alone));
+    }
+
+    #[test]
+    fn being_alone_is_reported_before_being_idle() {
+        let mut idle = IdleState::default();
+        idle.policy = IdlePolicy { idle_timeout: Some(5 * SECOND), alone_timeout: Some(5 * SECOND) };
+        idle.members = Some(HashSet::new());
+        let t0 = Instant::now();
+
+        idle.check(false, t0);
+        assert_eq!(idle.check(false, t0 + 5 * SECOND), Some(IdleReason::Alone));
+    }
+
+    #[test]
+    fn reset_restarts_the_timers() {
+        let mut idle = IdleState::default();
+        idle.policy.idle_timeout = Some(5 * SECOND);
+        let t0 = Instant::now();
+
+        idle.check(false, t0);
+        idle.reset();
+        assert_eq!(idle.check(false, t0 + 6 * SECOND), None);
+        assert_eq!(idle.check(false, t0 + 11 * SECOND), Some(IdleReason::Idle));
+    }
+
+    #[test]
+    fn without_timeouts_the_player_stays() {
+        let mut idle = IdleState::default();
+        idle.members = Some(HashSet::new());
+        let t0 = Instant::now();
+
+        idle.check(false, t0);
+        assert_eq!(idle.check(false, t0 + 3600 * SECOND), None);
+    }
+}
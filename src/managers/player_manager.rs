@@ -1,18 +1,132 @@
 use async_trait::async_trait;
-use ravalink_interconnect::protocol::{Message, Command};
+use log::error;
+use ravalink_interconnect::protocol::Command;
+use std::time::Duration;
+use crate::errors::InvalidArgumentSnafu;
+use crate::models::{CommandKind, PlayOptions, TrackInfo};
 use crate::{PlayerError, PlayerObject};
 
 #[async_trait]
 pub trait Player {
-    async fn play(&mut self, url: String) -> Result<Message, PlayerError>;
+    async fn play(&self, url: String) -> Result<TrackInfo, PlayerError>;
+    /// Plays `url` with clipping and replacement options. Returns `None` when
+    /// [`PlayOptions::no_replace`] is set and another track is still playing.
+    async fn play_with(
+        &self,
+        url: String,
+        options: PlayOptions,
+    ) -> Result<Option<TrackInfo>, PlayerError>;
+}
+
+impl PlayerObject {
+    pub(crate) fn validate_play(&self, url: &str, options: &PlayOptions) -> Result<(), PlayerError> {
+        let reason = if url.trim().is_empty() {
+            "url must not be empty"
+        } else if matches!((options.start_time, options.end_time), (Some(start), Some(end)) if end <= start) {
+            "end_time must be after start_time"
+        } else {
+            return Ok(());
+        };
+
+        InvalidArgumentSnafu {
+            guild_id: self.inner.guild_id,
+            command: CommandKind::Play,
+            reason,
+        }.fail()
+    }
+
+    /// Mutes the player ahead of a fade-in, returning the fade length and the
+    /// volume to fade back up to.
+    async fn prepare_fade_in(&self) -> Result<Option<(Duration, f32)>, PlayerError> {
+        let (fade_in, volume) = {
+            let fades = self.inner.fades.lock().await;
+            (fades.settings.fade_in, fades.volume)
+        };
+        if fade_in.is_zero() {
+            return Ok(None);
+        }
+        self.internal().send_volume(0.0).await?;
+        Ok(Some((fade_in, volume)))
+    }
+
+    /// Puts the volume back when the track a fade-in was prepared for never
+    /// started.
+    async fn cancel_fade_in(&self, fade: Option<(Duration, f32)>) {
+        if let Some((_, volume)) = fade {
+            if let Err(e) = self.internal().send_volume(volume).await {
+                error!("Failed to restore volume for guild {}: {}", self.inner.guild_id, e);
+            }
+        }
+    }
+
+    fn start_fade_in(&self, fade_in: Duration, volume: f32) {
+        let player = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = player.fade_to(volume, fade_in).await {
+                error!("Failed to fade in track for guild {}: {}", player.inner.guild_id, e);
+            }
+        });
+    }
 }
 
 #[async_trait]
 impl Player for PlayerObject {
-    async fn play(&mut self, url: String) -> Result<Message, PlayerError> {
-        self.send_request_with_response(
-            Command::Play { url },
+    async fn play(&self, url: String) -> Result<TrackInfo, PlayerError> {
+        self.validate_play(&url, &PlayOptions::default())?;
+        let fade = self.prepare_fade_in().await?;
+
+        let result = self.send_request_with_response(
+            Command::Play { url, start_time: None, end_time: None, no_replace: false },
+            None,
+        ).await.and_then(|response| self.decode_response(response, CommandKind::Play));
+        let track = match result {
+            Ok(track) => track,
+            Err(e) => {
+                self.cancel_fade_in(fade).await;
+                return Err(e);
+            }
+        };
+
+        if let Some((fade_in, volume)) = fade {
+            self.start_fade_in(fade_in, volume);
+        }
+        Ok(track)
+    }
+
+    async fn play_with(
+        &self,
+        url: String,
+        options: PlayOptions,
+    ) -> Result<Option<TrackInfo>, PlayerError> {
+        self.validate_play(&url, &options)?;
+        if options.no_replace && self.inner.state.borrow().track.is_some() {
+            return Ok(None);
+        }
+        let fade = self.prepare_fade_in().await?;
+
+        let result = self.send_request_with_response(
+            Command::Play {
+                url,
+                start_time: options.start_time.map(|t| t.as_millis() as u64),
+                end_time: options.end_time.map(|t| t.as_millis() as u64),
+                no_replace: options.no_replace,
+            },
             None,
-        ).await
+        ).await.and_then(|response| self.decode_response(response, CommandKind::Play));
+
+        match (result, fade) {
+            (Ok(Some(track)), Some((fade_in, volume))) => {
+                self.start_fade_in(fade_in, volume);
+                Ok(Some(track))
+            }
+            (Ok(track), fade) => {
+                self.cancel_fade_in(fade).await;
+                Ok(track)
+            }
+            (Err(e), fade) => {
+                self.cancel_fade_in(fade).await;
+                Err(e)
+            }
+        }
     }
 }
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+use crate::errors::InvalidArgumentSnafu;
+use crate::events::TrackEndReason;
+use crate::managers::player_manager::Player;
+use crate::models::{CommandKind, Enqueued, LoopMode, Playlist, QueuedTrack, TrackInfo};
+use crate::{PlayerError, PlayerObject};
+
+#[async_trait]
+pub trait QueueManager {
+    /// Appends `track` and returns the new queue length. Fails if the queue
+    /// already holds `max_queue_len` tracks.
+    async fn enqueue(&self, track: QueuedTrack) -> Result<usize, PlayerError>;
+    /// Appends `tracks` in order until the queue is full; the rest are
+    /// reported as rejected.
+    async fn enqueue_all(&self, tracks: Vec<QueuedTrack>) -> Enqueued;
+    /// Appends the tracks of a loaded playlist, as `enqueue_all` does.
+    async fn enqueue_playlist(&self, playlist: Playlist) -> Enqueued;
+    /// Caps how many tracks the queue may hold. Tracks already queued beyond
+    /// the new limit are kept.
+    async fn set_max_queue_len(&self, max: Option<usize>);
+    async fn max_queue_len(&self) -> Option<usize>;
+    async fn insert_at(&self, index: usize, track: QueuedTrack) -> Result<(), PlayerError>;
+    async fn remove(&self, index: usize) -> Result<QueuedTrack, PlayerError>;
+    async fn move_track(&self, from: usize, to: usize) -> Result<(), PlayerError>;
+    async fn clear(&self);
+    async fn shuffle(&self);
+    async fn list(&self) -> Vec<QueuedTrack>;
+    /// Plays the next queued track, or returns `None` if the queue is empty.
+    /// In [`LoopMode::Queue`] the skipped track goes to the back of the queue.
+    async fn skip(&self) -> Result<Option<TrackInfo>, PlayerError>;
+}
+
+impl PlayerObject {
+    /// How many more tracks fit in a queue of `len` tracks.
+    fn queue_room(&self, len: usize) -> usize {
+        match self.inner.state.borrow().max_queue_len {
+            Some(max) => max.saturating_sub(len),
+            None => usize::MAX,
+        }
+    }
+
+    fn queue_full_error(&self, len: usize) -> PlayerError {
+        InvalidArgumentSnafu {
+            guild_id: self.inner.guild_id,
+            command: CommandKind::Queue,
+            reason: format!("the queue is full at {} tracks", len),
+        }.build()
+    }
+
+    fn queue_index_error(&self, index: usize, len: usize) -> PlayerError {
+        InvalidArgumentSnafu {
+            guild_id: self.inner.guild_id,
+            command: CommandKind::Queue,
+            reason: format!("index {} is out of range for a queue of {} tracks", index, len),
+        }.build()
+    }
+
+    /// Starts the next track once `ended` has finished, honouring the loop
+    /// mode. A track that failed to load is neither replayed nor re-queued.
+    pub(crate) async fn advance(
+        &self,
+        ended: Option<TrackInfo>,
+        reason: &TrackEndReason,
+    ) -> Result<Option<TrackInfo>, PlayerError> {
+        let next = match reason {
+            TrackEndReason::LoadFailed => self.pop_next(None).await.map(|track| track.url),
+            _ => self.next_after(ended).await,
+        };
+        match next {
+            Some(url) => self.play(url).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Picks the URL that should follow `ended` under the current loop mode,
+    /// taking it off the queue if it came from there.
+    pub(crate) async fn next_after(&self, ended: Option<TrackInfo>) -> Option<String> {
+        let mode = self.inner.state.borrow().loop_mode;
+        match (mode, ended) {
+            (LoopMode::Track, Some(track)) => Some(track.url),
+            (LoopMode::Count(remaining), Some(track)) => {
+                self.inner.state.send_modify(|s| {
+                    s.loop_mode = if remaining > 1 { LoopMode::Count(remaining - 1) } else { LoopMode::Off };
+                });
+                Some(track.url)
+            }
+            (_, ended) => self.pop_next(ended).await.map(|track| track.url),
+        }
+    }
+
+    /// Returns the URL `next_after` would pick, without taking it.
+    pub(crate) async fn peek_after(&self, ended: Option<&TrackInfo>) -> Option<String> {
+        let mode = self.inner.state.borrow().loop_mode;
+        match (mode, ended) {
+            (LoopMode::Track | LoopMode::Count(_), Some(track)) => Some(track.url.clone()),
+            (LoopMode::Queue, Some(track)) => {
+                let queue = self.inner.queue.lock().await;
+                Some(queue.front().map_or(&track.url, |next| &next.url).clone())
+            }
+            _ => self.inner.queue.lock().await.front().map(|next| next.url.clone()),
+        }
+    }
+
+    async fn pop_next(&self, previous: Option<TrackInfo>) -> Option<QueuedTrack> {
+        let mut queue = self.inner.queue.lock().await;
+        if let (LoopMode::Queue, Some(track)) = (self.inner.state.borrow().loop_mode, previous) {
+            queue.push_back(track.into());
+        }
+        queue.pop_front()
+    }
+}
+
+#[async_trait]
+impl QueueManager for PlayerObject {
+    async fn enqueue(&self, track: QueuedTrack) -> Result<usize, PlayerError> {
+        let mut queue = self.inner.queue.lock().await;
+        if self.queue_room(queue.len()) == 0 {
+            return Err(self.queue_full_error(queue.len()));
+        }
+        queue.push_back(track);
+        Ok(queue.len())
+    }
+
+    async fn enqueue_all(&self, tracks: Vec<QueuedTrack>) -> Enqueued {
+        let mut queue = self.inner.queue.lock().await;
+        let added = tracks.len().min(self.queue_room(queue.len()));
+        let rejected = tracks.len() - added;
+        queue.extend(tracks.into_iter().take(added));
+        Enqueued { added, rejected }
+    }
+
+    async fn enqueue_playlist(&self, playlist: Playlist) -> Enqueued {
+        self.enqueue_all(playlist.tracks.into_iter().map(QueuedTrack::from).collect()).await
+    }
+
+    async fn set_max_queue_len(&self, max: Option<usize>) {
+        self.inner.state.send_modify(|s| s.max_queue_len = max);
+    }
+
+    async fn max_queue_len(&self) -> Option<usize> {
+        self.inner.state.borrow().max_queue_len
+    }
+
+    async fn insert_at(&self, index: usize, track: QueuedTrack) -> Result<(), PlayerError> {
+        let mut queue = self.inner.queue.lock().await;
+        if self.queue_room(queue.len()) == 0 {
+            return Err(self.queue_full_error(queue.len()));
+        }
+        if index > queue.len() {
+            return Err(self.queue_index_error(index, queue.len()));
+        }
+        queue.insert(index, track);
+        Ok(())
+    }
+
+    async fn remove(&self, index: usize) -> Result<QueuedTrack, PlayerError> {
+        let mut queue = self.inner.queue.lock().await;
+        let len = queue.len();
+        queue.remove(index).ok_or_else(|| self.queue_index_error(index, len))
+    }
+
+    async fn move_track(&self, from: usize, to: usize) -> Result<(), PlayerError> {
+        let mut queue = self.inner.queue.lock().await;
+        let len = queue.len();
+        if to >= len {
+            return Err(self.queue_index_error(to, len));
+        }
+        let track = queue.remove(from).ok_or_else(|| self.queue_index_error(from, len))?;
+        queue.insert(to, track);
+        Ok(())
+    }
+
+    async fn clear(&self) {
+        self.inner.queue.lock().await.clear();
+    }
+
+    async fn shuffle(&self) {
+        let mut queue = self.inner.queue.lock().await;
+        queue.make_contiguous().shuffle(&mut rand::thread_rng());
+    }
+
+    async fn list(&self) -> Vec<QueuedTrack> {
+        self.inner.queue.lock().await.iter().cloned().collect()
+    }
+
+    async fn skip(&self) -> Result<Option<TrackInfo>, PlayerError> {
+        let current = self.inner.state.borrow().track.clone();
+        match self.pop_next(current).await {
+            Some(track) => self.play(track.url).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{track, FakeNode};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn count_loop_replays_then_moves_on() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        player.inner.state.send_modify(|s| s.loop_mode = LoopMode::Count(2));
+        player.enqueue("b".to_string().into()).await.unwrap();
+
+        assert_eq!(player.next_after(Some(track("a"))).await.as_deref(), Some("a"));
+        assert_eq!(player.inner.state.borrow().loop_mode, LoopMode::Count(1));
+        assert_eq!(player.next_after(Some(track("a"))).await.as_deref(), Some("a"));
+        assert_eq!(player.inner.state.borrow().loop_mode, LoopMode::Off);
+        assert_eq!(player.next_after(Some(track("a"))).await.as_deref(), Some("b"));
+        assert_eq!(player.next_after(None).await, None);
+    }
+
+    #[tokio::test]
+    async fn queue_loop_sends_the_ended_track_to_the_back() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        player.inner.state.send_modify(|s| s.loop_mode = LoopMode::Queue);
+        player.enqueue("b".to_string().into()).await.unwrap();
+
+        assert_eq!(player.next_after(Some(track("a"))).await.as_deref(), Some("b"));
+        assert_eq!(player.next_after(Some(track("b"))).await.as_deref(), Some("a"));
+        // Nothing has ended yet, so there is nothing to send to the back.
+        player.clear().await;
+        assert_eq!(player.next_after(None).await, None);
+    }
+
+    #[tokio::test]
+    async fn the_queue_limit_holds_across_calls() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        player.set_max_queue_len(Some(3)).await;
+        let tracks = |urls: &[&str]| -> Vec<QueuedTrack> { urls.iter().map(|url| url.to_string().into()).collect() };
+
+        assert_eq!(player.enqueue_all(tracks(&["a", "b"])).await, Enqueued { added: 2, rejected: 0 });
+        assert_eq!(player.enqueue_all(tracks(&["c", "d", "e"])).await, Enqueued { added: 1, rejected: 2 });
+        assert!(player.enqueue("f".to_string().into()).await.is_err());
+        assert!(player.insert_at(0, "g".to_string().into()).await.is_err());
+        assert_eq!(player.list().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn playlists_are_queued_in_order() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        player.set_max_queue_len(Some(2)).await;
+        let playlist = Playlist {
+            name: "mix".to_string(),
+            tracks: vec![track("a"), track("b"), track("c")],
+            selected: None,
+            failures: Vec::new(),
+        };
+
+        assert_eq!(player.enqueue_playlist(playlist).await, Enqueued { added: 2, rejected: 1 });
+        let queue = player.list().await;
+        assert_eq!(queue.iter().map(|t| t.url.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(queue[0].info, Some(track("a")));
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use ravalink_interconnect::protocol::Command;
+use crate::errors::InvalidArgumentSnafu;
+use crate::models::{CommandKind, LoadResult, Playlist, SearchSource, TrackInfo};
+use crate::{PlayerError, PlayerObject};
+
+#[async_trait]
+pub trait SearchManager {
+    /// Resolves a URL, playlist URL or prefixed search such as `ytsearch:query`
+    /// without playing anything.
+    async fn resolve(&self, query: String) -> Result<LoadResult, PlayerError>;
+    /// Searches `source` for `query`. The node has no result limit, so it
+    /// returns every match and `limit` is applied to them here.
+    async fn search(
+        &self,
+        source: SearchSource,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<TrackInfo>, PlayerError>;
+    /// Loads a playlist URL, reporting entries that failed to load in
+    /// [`Playlist::failures`].
+    async fn load_playlist(&self, url: String) -> Result<Playlist, PlayerError>;
+}
+
+impl PlayerObject {
+    fn validate_query(&self, query: &str) -> Result<(), PlayerError> {
+        if !query.trim().is_empty() {
+            return Ok(());
+        }
+        InvalidArgumentSnafu {
+            guild_id: self.inner.guild_id,
+            command: CommandKind::Resolve,
+            reason: "query must not be empty",
+        }.fail()
+    }
+}
+
+#[async_trait]
+impl SearchManager for PlayerObject {
+    async fn resolve(&self, query: String) -> Result<LoadResult, PlayerError> {
+        self.validate_query(&query)?;
+
+        let response = self.send_request_with_response(
+            Command::Resolve { query },
+            None,
+        ).await?;
+        self.decode_response(response, CommandKind::Resolve)
+    }
+
+    async fn search(
+        &self,
+        source: SearchSource,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<TrackInfo>, PlayerError> {
+        // Checked before the prefix is added, which would make it non-empty.
+        self.validate_query(&query)?;
+        let query = format!("{}:{}", source.prefix(), query.trim());
+        let mut tracks = match self.resolve(query).await? {
+            LoadResult::Track(track) => vec![track],
+            LoadResult::Playlist(playlist) => playlist.tracks,
+            LoadResult::Search(tracks) => tracks,
+            LoadResult::Empty => Vec::new(),
+        };
+        tracks.truncate(limit);
+        Ok(tracks)
+    }
+
+    async fn load_playlist(&self, url: String) -> Result<Playlist, PlayerError> {
+        match self.resolve(url.clone()).await? {
+            LoadResult::Playlist(playlist) => Ok(playlist),
+            _ => InvalidArgumentSnafu {
+                guild_id: self.inner.guild_id,
+                command: CommandKind::Resolve,
+                reason: format!("{} is not a playlist", url),
+            }.fail(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{track, FakeNode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn blank_searches_are_rejected_before_the_prefix_is_added() {
+        let node = FakeNode::spawn(|_| Ok(json!({ "type": "empty" })));
+        let player = node.player().await;
+
+        let result = player.search(SearchSource::YouTube, "  ".to_string(), 5).await;
+        assert!(matches!(result, Err(PlayerError::InvalidArgument { .. })));
+        assert!(node.received().is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_results_are_limited_here() {
+        let node = FakeNode::spawn(|_| {
+            Ok(json!({ "type": "search", "data": [track("a"), track("b"), track("c")] }))
+        });
+        let player = node.player().await;
+
+        let tracks = player.search(SearchSource::SoundCloud, " song ".to_string(), 2).await.unwrap();
+        assert_eq!(tracks, vec![track("a"), track("b")]);
+        assert!(matches!(&node.received()[..], [Command::Resolve { query }] if query == "scsearch:song"));
+    }
+}
@@ -1,52 +1,184 @@
 use async_trait::async_trait;
-use ravalink_interconnect::protocol::{Command, Message};
+use ravalink_interconnect::protocol::Command;
 use std::time::Duration;
+use crate::errors::InvalidArgumentSnafu;
+use crate::models::{CommandKind, LoopMode, SeekOffset};
 use crate::PlayerObject;
 use crate::PlayerError;
 
 #[async_trait]
 pub trait TrackManager {
-    async fn set_volume(&self, playback_volume: f32) -> Result<Message, PlayerError>;
-    async fn loop_song(&self) -> Result<Message, PlayerError>;
-    async fn seek(&self, position: Duration) -> Result<Message, PlayerError>;
-    async fn resume(&self) -> Result<Message, PlayerError>;
-    async fn pause(&self) -> Result<Message, PlayerError>;
+    async fn set_volume(&self, playback_volume: f32) -> Result<f32, PlayerError>;
+    /// Sets how the player repeats tracks. Looping is enforced by the library
+    /// together with the queue when a track ends.
+    async fn set_loop_mode(&self, mode: LoopMode) -> LoopMode;
+    async fn loop_mode(&self) -> LoopMode;
+    async fn seek(&self, position: Duration) -> Result<Duration, PlayerError>;
+    /// Seeks relative to the current position. The target is clamped to the
+    /// start and end of the track. Unlike `seek`, rapid calls are not
+    /// coalesced into the last one.
+    async fn seek_by(&self, offset: SeekOffset) -> Result<Duration, PlayerError>;
+    async fn resume(&self) -> Result<(), PlayerError>;
+    async fn pause(&self) -> Result<(), PlayerError>;
 }
 
-#[async_trait]
-impl TrackManager for PlayerObject {
-    async fn set_volume(&self, playback_volume: f32) -> Result<Message, PlayerError> {
-        self.send_request_with_response(
-            Command::SetVolume { volume: playback_volume },
-            None,
-        ).await
+impl PlayerObject {
+    pub(crate) fn validate_volume(&self, volume: f32) -> Result<(), PlayerError> {
+        if volume.is_finite() && volume >= 0.0 {
+            return Ok(());
+        }
+        InvalidArgumentSnafu {
+            guild_id: self.inner.guild_id,
+            command: CommandKind::SetVolume,
+            reason: format!("volume must be a non-negative number, got {}", volume),
+        }.fail()
     }
 
-    async fn loop_song(&self) -> Result<Message, PlayerError> {
+    /// Sets the volume and returns the one applied, which middleware may
+    /// have changed on the way out.
+    pub(crate) async fn send_volume(&self, volume: f32) -> Result<f32, PlayerError> {
         self.send_request_with_response(
-            Command::Loop,
+            Command::SetVolume { volume },
             None,
-        ).await
+        ).await?;
+        Ok(self.inner.state.borrow().volume)
+    }
+}
+
+#[async_trait]
+impl TrackManager for PlayerObject {
+    async fn set_volume(&self, playback_volume: f32) -> Result<f32, PlayerError> {
+        self.validate_volume(playback_volume)?;
+
+        {
+            let mut fades = self.inner.fades.lock().await;
+            fades.generation += 1;
+            fades.volume = playback_volume;
+        }
+        let volume = self.send_volume(playback_volume).await?;
+        self.inner.fades.lock().await.volume = volume;
+        Ok(volume)
+    }
+
+    async fn set_loop_mode(&self, mode: LoopMode) -> LoopMode {
+        let mode = match mode {
+            LoopMode::Count(0) => LoopMode::Off,
+            mode => mode,
+        };
+        self.inner.state.send_modify(|s| s.loop_mode = mode);
+        mode
     }
 
-    async fn seek(&self, position: Duration) -> Result<Message, PlayerError> {
+    async fn loop_mode(&self) -> LoopMode {
+        self.inner.state.borrow().loop_mode
+    }
+
+    async fn seek(&self, position: Duration) -> Result<Duration, PlayerError> {
         self.send_request_with_response(
             Command::SeekToPosition { position: position.as_millis() as u64 },
             None,
-        ).await
+        ).await?;
+        Ok(self.inner.state.borrow().position)
     }
 
-    async fn resume(&self) -> Result<Message, PlayerError> {
+    async fn seek_by(&self, offset: SeekOffset) -> Result<Duration, PlayerError> {
+        let length = match &self.inner.state.borrow().track {
+            Some(track) => track.duration,
+            None => {
+                return InvalidArgumentSnafu {
+                    guild_id: self.inner.guild_id,
+                    command: CommandKind::SeekToPosition,
+                    reason: "nothing is playing",
+                }.fail();
+            }
+        };
+
+        let current = self.position();
+        let target = match offset {
+            SeekOffset::Forward(offset) => current.saturating_add(offset),
+            SeekOffset::Backward(offset) => current.saturating_sub(offset),
+        };
+        let target = if length.is_zero() { target } else { target.min(length) };
+
+        // Bypasses coalescing, which keeps only the last of several seeks.
+        self.run_command(
+            Command::SeekToPosition { position: target.as_millis() as u64 },
+            None,
+        ).await?;
+        Ok(self.inner.state.borrow().position)
+    }
+
+    async fn resume(&self) -> Result<(), PlayerError> {
         self.send_request_with_response(
             Command::Resume,
             None,
-        ).await
+        ).await?;
+        Ok(())
     }
 
-    async fn pause(&self) -> Result<Message, PlayerError> {
+    async fn pause(&self) -> Result<(), PlayerError> {
         self.send_request_with_response(
             Command::Pause,
             None,
-        ).await
+        ).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{CommandContext, CommandMiddleware};
+    use crate::test_support::{track, FakeNode};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn relative_seeks_stay_within_the_track() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player().await;
+        player.inner.state.send_modify(|s| {
+            s.track = Some(track("a"));
+            s.position = Duration::from_secs(10);
+            s.paused = true;
+        });
+
+        let back = player.seek_by(SeekOffset::Backward(Duration::from_secs(60))).await.unwrap();
+        assert_eq!(back, Duration::ZERO);
+        let forward = player.seek_by(SeekOffset::Forward(Duration::from_secs(600))).await.unwrap();
+        assert_eq!(forward, Duration::from_secs(180));
+        assert!(matches!(
+            &node.received()[..],
+            [Command::SeekToPosition { position: 0 }, Command::SeekToPosition { position: 180_000 }]
+        ));
     }
-}
\ No newline at end of file
+
+    /// Caps volumes at 1.0 and seeks at one minute.
+    struct Clamp;
+
+    #[async_trait]
+    impl CommandMiddleware for Clamp {
+        async fn before(&self, context: &mut CommandContext) -> Result<(), PlayerError> {
+            match &mut context.command {
+                Command::SetVolume { volume } => *volume = volume.min(1.0),
+                Command::SeekToPosition { position } => *position = (*position).min(60_000),
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn results_reflect_what_middleware_sent() {
+        let node = FakeNode::spawn(|_| Ok(Value::Null));
+        let player = node.player_with(Arc::new(vec![Arc::new(Clamp) as Arc<dyn CommandMiddleware>])).await;
+        player.inner.state.send_modify(|s| {
+            s.track = Some(track("a"));
+            s.paused = true;
+        });
+
+        assert_eq!(player.set_volume(3.0).await.unwrap(), 1.0);
+        assert_eq!(player.inner.fades.lock().await.volume, 1.0);
+        assert_eq!(player.seek(Duration::from_secs(90)).await.unwrap(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use ravalink_interconnect::protocol::Command;
+use std::num::NonZero;
+use crate::{PlayerError, PlayerObject};
+
+/// Discord voice credentials collected from the bot's gateway events.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct VoiceConnection {
+    pub(crate) channel_id: Option<NonZero<u64>>,
+    pub(crate) session_id: Option<String>,
+    pub(crate) token: Option<String>,
+    pub(crate) endpoint: Option<String>,
+}
+
+#[async_trait]
+pub trait VoiceManager {
+    /// Forwards the bot's own VOICE_STATE_UPDATE for this guild.
+    async fn update_voice_state(
+        &self,
+        session_id: String,
+        channel_id: Option<NonZero<u64>>,
+    ) -> Result<(), PlayerError>;
+    /// Forwards a VOICE_SERVER_UPDATE for this guild. A missing endpoint means
+    /// Discord is still allocating a voice server.
+    async fn update_voice_server(
+        &self,
+        token: String,
+        endpoint: Option<String>,
+    ) -> Result<(), PlayerError>;
+    /// Tracks another user's voice state so the idle policy knows whether the
+    /// bot is alone in its channel. Ignored until `set_channel_members` has
+    /// reported who was already in the channel.
+    async fn update_member_voice_state(
+        &self,
+        user_id: NonZero<u64>,
+        channel_id: Option<NonZero<u64>>,
+    );
+    /// Replaces the known members of the player's voice channel, excluding the bot.
+    async fn set_channel_members(&self, members: Vec<NonZero<u64>>);
+}
+
+impl PlayerObject {
+    /// Sends the voice credentials to the node once all of them are known.
+    async fn send_voice_update(&self) -> Result<(), PlayerError> {
+        let voice = self.inner.voice.lock().await.clone();
+        let (Some(channel_id), Some(session_id), Some(token), Some(endpoint)) =
+            (voice.channel_id, voice.session_id, voice.token, voice.endpoint)
+        else {
+            return Ok(());
+        };
+
+        self.send_request_with_response(
+            Command::VoiceUpdate { session_id, token, endpoint },
+            Some(channel_id),
+        ).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VoiceManager for PlayerObject {
+    async fn update_voice_state(
+        &self,
+        session_id: String,
+        channel_id: Option<NonZero<u64>>,
+    ) -> Result<(), PlayerError> {
+        {
+            let mut voice = self.inner.voice.lock().await;
+            if voice.channel_id != channel_id {
+                self.inner.idle.lock().await.members = None;
+            }
+            voice.channel_id = channel_id;
+            voice.session_id = Some(session_id);
+        }
+
+        if channel_id.is_none() {
+            self.inner.state.send_modify(|s| s.voice_channel_id = None);
+            return Ok(());
+        }
+        self.send_voice_update().await
+    }
+
+    async fn update_voice_server(
+        &self,
+        token: String,
+        endpoint: Option<String>,
+    ) -> Result<(), PlayerError> {
+        {
+            let mut voice = self.inner.voice.lock().await;
+            voice.token = Some(token);
+            voice.endpoint = endpoint;
+        }
+        self.send_voice_update().await
+    }
+
+    async fn update_member_voice_state(
+        &self,
+        user_id: NonZero<u64>,
+        channel_id: Option<NonZero<u64>>,
+    ) {
+        let player_channel = self.inner.state.borrow().voice_channel_id;
+        let mut idle = self.inner.idle.lock().await;
+        // Without the members already present, a join followed by a leave
+        // would look like the bot was left alone.
+        let Some(members) = idle.members.as_mut() else {
+            return;
+        };
+        if player_channel.is_some() && channel_id == player_channel {
+            members.insert(user_id.get());
+        } else {
+            members.remove(&user_id.get());
+        }
+    }
+
+    async fn set_channel_members(&self, members: Vec<NonZero<u64>>) {
+        self.inner.idle.lock().await.members = Some(members.into_iter().map(NonZero::get).collect());
+    }
+}
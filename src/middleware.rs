@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use ravalink_interconnect::protocol::{Command, Response};
+use std::num::NonZero;
+use std::sync::Arc;
+use crate::PlayerError;
+
+/// The layers every command passes through, in registration order.
+pub(crate) type MiddlewareChain = Arc<Vec<Arc<dyn CommandMiddleware>>>;
+
+/// Who issued a command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandOrigin {
+    /// Called through the public API.
+    #[default]
+    User,
+    /// Sent by the library on its own, such as fade steps, queue advances,
+    /// crossfades, idle disconnects and reattaching after a restart.
+    Internal,
+}
+
+/// A command on its way to the node.
+#[derive(Clone, Debug)]
+pub struct CommandContext {
+    pub guild_id: NonZero<u64>,
+    pub voice_channel_id: Option<NonZero<u64>>,
+    pub command: Command,
+    pub origin: CommandOrigin,
+}
+
+/// A layer around every command a player sends. Layers are registered through
+/// `RavalinkConfig::middleware` and run in order, both before the command is
+/// sent and after its result is known.
+#[async_trait]
+pub trait CommandMiddleware: Send + Sync {
+    /// Runs before the command is sent and may rewrite it. Returning an error
+    /// stops the command; later layers and the node never see it.
+    async fn before(&self, _context: &mut CommandContext) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    /// Runs once the command has completed or failed. Returning an error
+    /// replaces the result the caller gets.
+    async fn after(
+        &self,
+        _context: &CommandContext,
+        _result: &Result<Response, PlayerError>,
+    ) -> Result<(), PlayerError> {
+        Ok(())
+    }
+}
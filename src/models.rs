@@ -0,0 +1,238 @@
+use ravalink_interconnect::protocol::Command;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::num::NonZero;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub url: String,
+    pub title: String,
+    pub author: String,
+    /// Zero for live streams.
+    #[serde(with = "crate::helpers::millis")]
+    pub duration: Duration,
+    #[serde(default)]
+    pub artwork_url: Option<String>,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub is_seekable: bool,
+    #[serde(default)]
+    pub is_stream: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub tracks: Vec<TrackInfo>,
+    /// Index of the track the playlist URL pointed at, if any.
+    #[serde(default)]
+    pub selected: Option<usize>,
+    /// Playlist entries the node could not load.
+    #[serde(default)]
+    pub failures: Vec<TrackLoadFailure>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackLoadFailure {
+    /// Position of the entry in the original playlist.
+    pub index: usize,
+    #[serde(default)]
+    pub url: Option<String>,
+    pub reason: String,
+}
+
+/// What a bulk enqueue did: how many tracks it added, and how many it left
+/// out because the queue was full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Enqueued {
+    pub added: usize,
+    pub rejected: usize,
+}
+
+/// What the node found for a query passed to `resolve`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum LoadResult {
+    Track(TrackInfo),
+    Playlist(Playlist),
+    Search(Vec<TrackInfo>),
+    Empty,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchSource {
+    YouTube,
+    YouTubeMusic,
+    SoundCloud,
+    /// Any other search prefix the node understands, without the trailing colon.
+    Custom(String),
+}
+
+impl SearchSource {
+    pub fn prefix(&self) -> &str {
+        match self {
+            SearchSource::YouTube => "ytsearch",
+            SearchSource::YouTubeMusic => "ytmsearch",
+            SearchSource::SoundCloud => "scsearch",
+            SearchSource::Custom(prefix) => prefix,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionInfo {
+    pub guild_id: NonZero<u64>,
+    pub voice_channel_id: NonZero<u64>,
+}
+
+/// A seek relative to the current position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekOffset {
+    Forward(Duration),
+    Backward(Duration),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopMode {
+    #[default]
+    Off,
+    Track,
+    Queue,
+    /// Repeats the current track this many more times, then turns looping off.
+    Count(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandKind {
+    Connect,
+    Stop,
+    Play,
+    SetVolume,
+    Loop,
+    SeekToPosition,
+    Resume,
+    Pause,
+    Resolve,
+    SetFilters,
+    Crossfade,
+    VoiceUpdate,
+    Disconnect,
+    Destroy,
+    Reattach,
+    Batch,
+    Ping,
+    Queue,
+}
+
+impl CommandKind {
+    /// Whether the command needs the player to be in a voice channel.
+    pub fn requires_connection(&self) -> bool {
+        !matches!(
+            self,
+            CommandKind::Connect
+                | CommandKind::VoiceUpdate
+                | CommandKind::Disconnect
+                | CommandKind::Destroy
+                | CommandKind::Reattach
+                | CommandKind::Batch
+                | CommandKind::Stop
+                | CommandKind::Resolve
+                | CommandKind::Ping
+                | CommandKind::Queue
+        )
+    }
+
+    /// Whether rapid commands of this kind are folded into the latest one.
+    /// Relative seeks are sent directly, since folding them would drop all
+    /// but the last offset.
+    pub fn coalesces(&self) -> bool {
+        matches!(self, CommandKind::SetVolume | CommandKind::SeekToPosition)
+    }
+}
+
+impl From<&Command> for CommandKind {
+    fn from(command: &Command) -> Self {
+        match command {
+            Command::Connect => CommandKind::Connect,
+            Command::Stop => CommandKind::Stop,
+            Command::Play { .. } => CommandKind::Play,
+            Command::SetVolume { .. } => CommandKind::SetVolume,
+            Command::Loop => CommandKind::Loop,
+            Command::SeekToPosition { .. } => CommandKind::SeekToPosition,
+            Command::Resume => CommandKind::Resume,
+            Command::Pause => CommandKind::Pause,
+            Command::Resolve { .. } => CommandKind::Resolve,
+            Command::SetFilters { .. } => CommandKind::SetFilters,
+            Command::Crossfade { .. } => CommandKind::Crossfade,
+            Command::VoiceUpdate { .. } => CommandKind::VoiceUpdate,
+            Command::Disconnect => CommandKind::Disconnect,
+            Command::Destroy => CommandKind::Destroy,
+            Command::Reattach { .. } => CommandKind::Reattach,
+            Command::Batch { .. } => CommandKind::Batch,
+        }
+    }
+}
+
+impl fmt::Display for CommandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueuedTrack {
+    pub url: String,
+    pub info: Option<TrackInfo>,
+}
+
+impl From<String> for QueuedTrack {
+    fn from(url: String) -> Self {
+        QueuedTrack { url, info: None }
+    }
+}
+
+impl From<TrackInfo> for QueuedTrack {
+    fn from(info: TrackInfo) -> Self {
+        QueuedTrack { url: info.url.clone(), info: Some(info) }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlayOptions {
+    /// Position to start the track from.
+    pub start_time: Option<Duration>,
+    /// Position at which the node ends the track as if it had finished.
+    pub end_time: Option<Duration>,
+    /// Leave the current track playing instead of replacing it.
+    pub no_replace: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FadeSettings {
+    /// Fade-in applied to tracks started with `play`.
+    pub fade_in: Duration,
+    /// Fade-out applied before `stop`.
+    pub fade_out: Duration,
+    /// How long consecutive tracks from the queue overlap. Zero cuts hard.
+    pub crossfade: Duration,
+}
+
+/// When a player should leave its voice channel on its own. `None` disables
+/// the corresponding check.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IdlePolicy {
+    /// Leave after nothing has played for this long.
+    pub idle_timeout: Option<Duration>,
+    /// Leave after the bot has been alone in the channel for this long. Only
+    /// applies once the channel's members have been reported with
+    /// `VoiceManager::set_channel_members`.
+    pub alone_timeout: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleReason {
+    Idle,
+    Alone,
+}
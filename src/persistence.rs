@@ -0,0 +1,304 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::errors::{PlayerError, StoreError, StoreIoSnafu, StoreSerializationSnafu};
+use crate::middleware::MiddlewareChain;
+use crate::models::QueuedTrack;
+use crate::state::PlayerState;
+use crate::background::processor::RavalinkIPC;
+use crate::background::registry::Registry;
+use crate::PlayerObject;
+use futures::future::join_all;
+use log::error;
+use nanoid::nanoid;
+use ravalink_interconnect::protocol::Command;
+use tokio::sync::broadcast::Sender;
+
+/// Everything needed to restore a player after the bot restarts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub guild_id: NonZero<u64>,
+    pub state: PlayerState,
+    pub queue: Vec<QueuedTrack>,
+}
+
+/// Where the session id and player snapshots are kept between restarts.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn load_session_id(&self) -> Result<Option<String>, StoreError>;
+    async fn save_session_id(&self, session_id: &str) -> Result<(), StoreError>;
+    async fn load_players(&self) -> Result<Vec<PlayerSnapshot>, StoreError>;
+    async fn save_player(&self, snapshot: &PlayerSnapshot) -> Result<(), StoreError>;
+    async fn remove_player(&self, guild_id: NonZero<u64>) -> Result<(), StoreError>;
+}
+
+/// The store and session a client persists its players to. Each client has
+/// its own, shared by all of its players.
+#[derive(Clone)]
+pub(crate) struct Session {
+    pub(crate) session_id: String,
+    pub(crate) store: Arc<dyn StateStore>,
+}
+
+impl PlayerObject {
+    pub(crate) async fn snapshot(&self) -> PlayerSnapshot {
+        PlayerSnapshot {
+            guild_id: self.inner.guild_id,
+            state: self.state(),
+            queue: self.inner.queue.lock().await.iter().cloned().collect(),
+        }
+    }
+
+    /// Saves the player if it changed since `last_saved`, returning what was saved.
+    pub(crate) async fn persist(&self, last_saved: Option<PlayerSnapshot>) -> Option<PlayerSnapshot> {
+        let session = self.inner.session.as_ref()?;
+        let snapshot = self.snapshot().await;
+        if last_saved.as_ref() == Some(&snapshot) {
+            return last_saved;
+        }
+        if let Err(e) = session.store.save_player(&snapshot).await {
+            error!("Failed to save player for guild {}: {}", self.inner.guild_id, e);
+            return last_saved;
+        }
+        Some(snapshot)
+    }
+
+    pub(crate) async fn forget(&self) {
+        if let Some(session) = &self.inner.session {
+            if let Err(e) = session.store.remove_player(self.inner.guild_id).await {
+                error!("Failed to remove saved player for guild {}: {}", self.inner.guild_id, e);
+            }
+        }
+    }
+
+    /// Restores a saved player and reattaches it to the node's player for the
+    /// same guild, which keeps playing while the bot restarts.
+    pub(crate) async fn reattach(
+        &self,
+        session_id: String,
+        snapshot: PlayerSnapshot,
+    ) -> Result<(), PlayerError> {
+        self.inner.state.send_replace(snapshot.state);
+        *self.inner.queue.lock().await = snapshot.queue.into();
+
+        self.send_request_with_response(
+            Command::Reattach { session_id },
+            None,
+        ).await?;
+        Ok(())
+    }
+}
+
+/// Loads the session to resume from `store`, or starts a new one, and saves
+/// its id.
+pub(crate) async fn open_session(store: Arc<dyn StateStore>, session_id: Option<String>) -> Session {
+    let session_id = match session_id {
+        Some(session_id) => session_id,
+        None => match store.load_session_id().await {
+            Ok(Some(session_id)) => session_id,
+            Ok(None) => nanoid!(),
+            Err(e) => {
+                error!("Failed to load the saved session id: {}", e);
+                nanoid!()
+            }
+        },
+    };
+    if let Err(e) = store.save_session_id(&session_id).await {
+        error!("Failed to save the session id: {}", e);
+    }
+    Session { session_id, store }
+}
+
+/// Reattaches every player saved in `session`, adding the ones the node still
+/// knows to `players`.
+pub(crate) async fn restore_players(
+    session: &Session,
+    players: &Arc<Registry>,
+    tx: &Sender<RavalinkIPC>,
+    middleware: &MiddlewareChain,
+) {
+    let store = &session.store;
+    let snapshots = match store.load_players().await {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            error!("Failed to load saved players: {}", e);
+            return;
+        }
+    };
+
+    let restores = snapshots.into_iter().map(|snapshot| async move {
+        let player = PlayerObject::new(
+            snapshot.guild_id,
+            tx.clone(),
+            middleware.clone(),
+            Some(session.clone()),
+            Arc::downgrade(players),
+        ).await?;
+        if let Err(e) = player.internal().reattach(session.session_id.clone(), snapshot).await {
+            player.inner.shutdown.notify_one();
+            return Err(e);
+        }
+        Ok::<_, PlayerError>(player)
+    });
+
+    for result in join_all(restores).await {
+        match result {
+            Ok(player) => {
+                let mut players = players.write().await;
+                if players.contains_key(&player.inner.guild_id) {
+                    // The bot created a player for the guild while this one
+                    // was being restored; keep the one it is using.
+                    player.inner.shutdown.notify_one();
+                } else {
+                    players.insert(player.inner.guild_id, player);
+                }
+            }
+            Err(e) => {
+                error!("Failed to reattach player: {}", e);
+                if let (Some(guild_id), false) = (e.guild_id(), e.is_retryable()) {
+                    if let Err(e) = store.remove_player(guild_id).await {
+                        error!("Failed to remove saved player for guild {}: {}", guild_id, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Keeps snapshots in memory, so players survive re-initializing the client
+/// within one process.
+#[derive(Default)]
+pub struct MemoryStateStore {
+    session_id: Mutex<Option<String>>,
+    players: Mutex<HashMap<NonZero<u64>, PlayerSnapshot>>,
+}
+
+impl MemoryStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryStateStore {
+    async fn load_session_id(&self) -> Result<Option<String>, StoreError> {
+        Ok(self.session_id.lock().await.clone())
+    }
+
+    async fn save_session_id(&self, session_id: &str) -> Result<(), StoreError> {
+        *self.session_id.lock().await = Some(session_id.to_string());
+        Ok(())
+    }
+
+    async fn load_players(&self) -> Result<Vec<PlayerSnapshot>, StoreError> {
+        Ok(self.players.lock().await.values().cloned().collect())
+    }
+
+    async fn save_player(&self, snapshot: &PlayerSnapshot) -> Result<(), StoreError> {
+        self.players.lock().await.insert(snapshot.guild_id, snapshot.clone());
+        Ok(())
+    }
+
+    async fn remove_player(&self, guild_id: NonZero<u64>) -> Result<(), StoreError> {
+        self.players.lock().await.remove(&guild_id);
+        Ok(())
+    }
+}
+
+/// Keeps the session id and one JSON file per player in a directory. Files
+/// that cannot be read are logged and skipped when loading.
+pub struct FileStateStore {
+    directory: PathBuf,
+}
+
+impl FileStateStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        FileStateStore { directory: directory.into() }
+    }
+
+    fn session_path(&self) -> PathBuf {
+        self.directory.join("session_id")
+    }
+
+    fn player_path(&self, guild_id: NonZero<u64>) -> PathBuf {
+        self.directory.join(format!("{}.json", guild_id))
+    }
+
+    /// Writes through a temporary file, so a crash mid-write leaves the old
+    /// contents in place rather than a truncated file.
+    async fn write_atomic(&self, path: &Path, data: &[u8]) -> Result<(), StoreError> {
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .context(StoreIoSnafu { path: self.directory.clone() })?;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        tokio::fs::write(&temp, data).await.context(StoreIoSnafu { path: temp.clone() })?;
+        tokio::fs::rename(&temp, path).await.context(StoreIoSnafu { path })
+    }
+}
+
+async fn read_snapshot(path: PathBuf) -> Result<PlayerSnapshot, StoreError> {
+    let data = tokio::fs::read(&path).await.context(StoreIoSnafu { path })?;
+    serde_json::from_slice(&data).context(StoreSerializationSnafu)
+}
+
+#[async_trait]
+impl StateStore for FileStateStore {
+    async fn load_session_id(&self) -> Result<Option<String>, StoreError> {
+        let path = self.session_path();
+        match tokio::fs::read_to_string(&path).await {
+            Ok(session_id) => Ok(Some(session_id.trim().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(StoreIoSnafu { path }),
+        }
+    }
+
+    async fn save_session_id(&self, session_id: &str) -> Result<(), StoreError> {
+        self.write_atomic(&self.session_path(), session_id.as_bytes()).await
+    }
+
+    async fn load_players(&self) -> Result<Vec<PlayerSnapshot>, StoreError> {
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(StoreIoSnafu { path: self.directory.clone() }),
+        };
+
+        let mut snapshots = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context(StoreIoSnafu { path: self.directory.clone() })?
+        {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            // One unreadable snapshot must not keep the others from loading.
+            match read_snapshot(path.clone()).await {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => error!("Skipping saved player {}: {}", path.display(), e),
+            }
+        }
+        Ok(snapshots)
+    }
+
+    async fn save_player(&self, snapshot: &PlayerSnapshot) -> Result<(), StoreError> {
+        let data = serde_json::to_vec(snapshot).context(StoreSerializationSnafu)?;
+        self.write_atomic(&self.player_path(snapshot.guild_id), &data).await
+    }
+
+    async fn remove_player(&self, guild_id: NonZero<u64>) -> Result<(), StoreError> {
+        let path = self.player_path(guild_id);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).context(StoreIoSnafu { path }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::num::NonZero;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::errors::RateLimitedSnafu;
+use crate::middleware::{CommandContext, CommandMiddleware, CommandOrigin};
+use crate::models::CommandKind;
+use crate::PlayerError;
+
+/// Buckets kept per guild before full ones are dropped.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// A token bucket: `burst` commands at once, refilled by one every `interval`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        RateLimit { burst, interval }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Fail with `PlayerError::RateLimited`.
+    #[default]
+    Reject,
+    /// Wait for a token, failing only if that would take longer than `max_wait`.
+    Delay { max_wait: Duration },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitConfig {
+    /// Shared by every guild.
+    pub global: Option<RateLimit>,
+    /// Applied to each guild separately.
+    pub per_guild: Option<RateLimit>,
+    /// Applied to each command kind of each guild separately.
+    pub per_command: HashMap<CommandKind, RateLimit>,
+    pub policy: RateLimitPolicy,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Bucket { tokens: limit.burst as f64, updated: now }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        let refilled = if limit.interval.is_zero() {
+            f64::INFINITY
+        } else {
+            elapsed.as_secs_f64() / limit.interval.as_secs_f64()
+        };
+        self.tokens = (self.tokens + refilled).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// How long until a token is available.
+    fn wait(&self, limit: &RateLimit) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if limit.burst == 0 {
+            Duration::MAX
+        } else {
+            limit.interval.mul_f64(1.0 - self.tokens)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    global: Option<Bucket>,
+    guilds: HashMap<NonZero<u64>, Bucket>,
+    commands: HashMap<(NonZero<u64>, CommandKind), Bucket>,
+}
+
+/// Middleware that limits how fast commands are sent. Add it to
+/// `RavalinkConfig::middleware`, usually as the first layer. Commands the
+/// library sends on its own are not counted.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter { config, buckets: Mutex::new(Buckets::default()) }
+    }
+
+    /// Takes a token from every bucket that applies, or returns how long to
+    /// wait if any of them is empty. Nothing is taken unless all have one.
+    fn try_acquire(&self, guild_id: NonZero<u64>, command: CommandKind, now: Instant) -> Result<(), Duration> {
+        let mut guard = self.buckets.lock().unwrap();
+        let Buckets { global, guilds, commands } = &mut *guard;
+
+        if guilds.len() > PRUNE_THRESHOLD {
+            if let Some(limit) = &self.config.per_guild {
+                guilds.retain(|_, b| {
+                    b.refill(limit, now);
+                    b.tokens < limit.burst as f64
+                });
+            }
+        }
+        if commands.len() > PRUNE_THRESHOLD {
+            commands.retain(|(_, kind), b| match self.config.per_command.get(kind) {
+                Some(limit) => {
+                    b.refill(limit, now);
+                    b.tokens < limit.burst as f64
+                }
+                None => false,
+            });
+        }
+
+        let mut buckets: Vec<(&mut Bucket, &RateLimit)> = Vec::with_capacity(3);
+        if let Some(limit) = &self.config.global {
+            buckets.push((global.get_or_insert_with(|| Bucket::full(limit, now)), limit));
+        }
+        if let Some(limit) = &self.config.per_guild {
+            buckets.push((guilds.entry(guild_id).or_insert_with(|| Bucket::full(limit, now)), limit));
+        }
+        if let Some(limit) = self.config.per_command.get(&command) {
+            let bucket = commands.entry((guild_id, command)).or_insert_with(|| Bucket::full(limit, now));
+            buckets.push((bucket, limit));
+        }
+
+        let mut wait = Duration::ZERO;
+        for (bucket, limit) in buckets.iter_mut() {
+            bucket.refill(limit, now);
+            wait = wait.max(bucket.wait(limit));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (bucket, _) in buckets {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CommandMiddleware for RateLimiter {
+    async fn before(&self, context: &mut CommandContext) -> Result<(), PlayerError> {
+        if context.origin == CommandOrigin::Internal {
+            return Ok(());
+        }
+        let command = CommandKind::from(&context.command);
+        let started = Instant::now();
+        loop {
+            let retry_after = match self.try_acquire(context.guild_id, command, Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(retry_after) => retry_after,
+            };
+            match self.config.policy {
+                RateLimitPolicy::Delay { max_wait }
+                    if started.elapsed().checked_add(retry_after).is_some_and(|t| t <= max_wait) =>
+                {
+                    tokio::time::sleep(retry_after).await;
+                }
+                _ => {
+                    return RateLimitedSnafu { guild_id: context.guild_id, command, retry_after }.fail();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn guild(id: u64) -> NonZero<u64> {
+        NonZero::new(id).unwrap()
+    }
+
+    #[test]
+    fn bucket_refills_up_to_burst() {
+        let limit = RateLimit::new(3, Duration::from_secs(1));
+        let t0 = Instant::now();
+        let mut bucket = Bucket::full(&limit, t0);
+        bucket.tokens = 0.0;
+
+        bucket.refill(&limit, t0 + Duration::from_millis(1500));
+        assert_eq!(bucket.tokens, 1.5);
+        bucket.refill(&limit, t0 + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 3.0);
+    }
+
+    #[test]
+    fn bucket_wait_covers_the_missing_fraction() {
+        let limit = RateLimit::new(1, Duration::from_secs(2));
+        let mut bucket = Bucket::full(&limit, Instant::now());
+        assert_eq!(bucket.wait(&limit), Duration::ZERO);
+
+        bucket.tokens = 0.25;
+        assert_eq!(bucket.wait(&limit), Duration::from_millis(1500));
+        assert_eq!(bucket.wait(&RateLimit::new(0, Duration::from_secs(1))), Duration::MAX);
+    }
+
+    #[test]
+    fn tokens_come_back_over_time() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_guild: Some(RateLimit::new(2, Duration::from_secs(1))),
+            ..RateLimitConfig::default()
+        });
+        let t0 = Instant::now();
+
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, t0).is_ok());
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, t0).is_ok());
+        assert_eq!(
+            limiter.try_acquire(guild(1), CommandKind::Play, t0 + Duration::from_millis(250)),
+            Err(Duration::from_millis(750)),
+        );
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, t0 + Duration::from_secs(1)).is_ok());
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, t0 + Duration::from_secs(1)).is_err());
+        // A long pause refills the bucket only up to its burst.
+        let later = t0 + Duration::from_secs(60);
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, later).is_ok());
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, later).is_ok());
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, later).is_err());
+    }
+
+    #[test]
+    fn nothing_is_taken_unless_every_bucket_has_a_token() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            global: Some(RateLimit::new(2, HOUR)),
+            per_guild: Some(RateLimit::new(1, HOUR)),
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, now).is_ok());
+        // The guild bucket is empty, so the global token must be left alone.
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, now).is_err());
+        assert!(limiter.try_acquire(guild(2), CommandKind::Play, now).is_ok());
+        assert!(limiter.try_acquire(guild(3), CommandKind::Play, now).is_err());
+    }
+
+    #[test]
+    fn command_limits_are_kept_per_guild_and_kind() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_command: HashMap::from([(CommandKind::SetVolume, RateLimit::new(1, HOUR))]),
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire(guild(1), CommandKind::SetVolume, now).is_ok());
+        assert!(limiter.try_acquire(guild(1), CommandKind::SetVolume, now).is_err());
+        assert!(limiter.try_acquire(guild(1), CommandKind::Play, now).is_ok());
+        assert!(limiter.try_acquire(guild(2), CommandKind::SetVolume, now).is_ok());
+    }
+
+    #[test]
+    fn internal_commands_are_not_limited() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_guild: Some(RateLimit::new(0, HOUR)),
+            ..RateLimitConfig::default()
+        });
+        let mut context = CommandContext {
+            guild_id: guild(1),
+            voice_channel_id: None,
+            command: ravalink_interconnect::protocol::Command::Pause,
+            origin: CommandOrigin::Internal,
+        };
+
+        assert!(futures::executor::block_on(limiter.before(&mut context)).is_ok());
+        context.origin = CommandOrigin::User;
+        assert!(futures::executor::block_on(limiter.before(&mut context)).is_err());
+    }
+
+    #[test]
+    fn full_buckets_are_pruned() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_guild: Some(RateLimit::new(1, Duration::from_secs(1))),
+            ..RateLimitConfig::default()
+        });
+        let t0 = Instant::now();
+        for id in 1..=PRUNE_THRESHOLD as u64 + 1 {
+            limiter.try_acquire(guild(id), CommandKind::Play, t0).unwrap();
+        }
+        // Every bucket has refilled by now, so all but the new one are dropped.
+        limiter.try_acquire(guild(1), CommandKind::Play, t0 + Duration::from_secs(2)).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().guilds.len(), 1);
+    }
+}
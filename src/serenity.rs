@@ -1,16 +1,20 @@
 use futures::executor;
 use std::sync::Arc;
 
-use crate::{init_ravalink, Ravalink, RavalinkConfig};
+use std::num::NonZero;
+use crate::managers::voice_manager::VoiceManager;
+use crate::{init_ravalink, PlayerError, Ravalink, RavalinkConfig};
+use serenity::model::event::VoiceServerUpdateEvent;
+use serenity::model::id::UserId;
+use serenity::model::voice::VoiceState;
 use serenity::prelude::TypeMapKey;
 pub use serenity::client::ClientBuilder;
 use serenity::*;
-use tokio::sync::Mutex;
 
 pub struct RavalinkKey;
 
 impl TypeMapKey for RavalinkKey {
-    type Value = Arc<Mutex<Ravalink>>;
+    type Value = Arc<Ravalink>;
 }
 
 pub trait SerenityInit {
@@ -25,28 +29,72 @@ impl SerenityInit for ClientBuilder {
     }
 }
 
+/// Returns the client registered with `register_ravalink`. The type map lock
+/// is released before this returns.
+pub async fn get(ctx: &serenity::client::Context) -> Option<Arc<Ravalink>> {
+    ctx.data.read().await.get::<RavalinkKey>().cloned()
+}
+
+/// Forwards a voice state to the player of its guild. Call this from
+/// `EventHandler::voice_state_update` for every user: the bot's own state is
+/// sent to the node, other users' states feed the idle policy once the
+/// channel has been seeded with `VoiceManager::set_channel_members`.
+pub async fn forward_voice_state(
+    ravalink: &Ravalink,
+    bot_user_id: UserId,
+    voice_state: &VoiceState,
+) -> Result<(), PlayerError> {
+    let Some(guild_id) = voice_state.guild_id else {
+        return Ok(());
+    };
+
+    let Some(player) = ravalink.get_player(guild_id.into()).await else {
+        return Ok(());
+    };
+
+    let channel_id = voice_state.channel_id.and_then(|c| NonZero::new(c.get()));
+    if voice_state.user_id == bot_user_id {
+        player.update_voice_state(voice_state.session_id.clone(), channel_id).await
+    } else {
+        if let Some(user_id) = NonZero::new(voice_state.user_id.get()) {
+            player.update_member_voice_state(user_id, channel_id).await;
+        }
+        Ok(())
+    }
+}
+
+/// Forwards a voice server update to the player of its guild. Call this from
+/// `EventHandler::voice_server_update`.
+pub async fn forward_voice_server(
+    ravalink: &Ravalink,
+    event: &VoiceServerUpdateEvent,
+) -> Result<(), PlayerError> {
+    let Some(guild_id) = event.guild_id else {
+        return Ok(());
+    };
+
+    match ravalink.get_player(guild_id.into()).await {
+        Some(player) => player.update_voice_server(event.token.clone(), event.endpoint.clone()).await,
+        None => Ok(()),
+    }
+}
 
+/// Same as [`get_handler_from_interaction`], which it forwards to.
+///
+/// Deprecated: use [`get_handler_from_interaction`] instead.
 #[macro_export]
 macro_rules! get_handler_from_interaction_mutable {
     ($ctx: expr, $interaction: expr, $reference: ident) => {
-        let r = $ctx.data.read().await;
-        let guild_id = match $interaction.guild_id {
-            Some(gid) => gid,
-            None => {
-                eprintln!("No guild ID found in interaction");
-                return Ok(());
-            }
-        };
-        let manager = r.get::<RavalinkKey>();
-        let mut mx = manager.unwrap().lock().await;
-        let mut players = mx.players.write().await;
-        $reference = players.get_mut(&guild_id.to_string());
+        $crate::get_handler_from_interaction!($ctx, $interaction, $reference);
     };
 }
+
+/// Sets `$reference` to the player of the interaction's guild, or `None` if
+/// the guild has no player or `register_ravalink` was never called. Returns
+/// `Ok(())` from the enclosing function if the interaction has no guild.
 #[macro_export]
 macro_rules! get_handler_from_interaction {
     ($ctx: expr, $interaction: expr, $reference: ident) => {
-        let r = $ctx.data.read().await;
         let guild_id = match $interaction.guild_id {
             Some(gid) => gid,
             None => {
@@ -54,9 +102,12 @@ macro_rules! get_handler_from_interaction {
                 return Ok(());
             }
         };
-        let manager = r.get::<RavalinkKey>();
-        let mut mx = manager.unwrap().lock().await;
-        let mut players = mx.players.write().await;
-        $reference = players.get_mut(&guild_id.to_string());
+        $reference = match $crate::serenity::get(&$ctx).await {
+            Some(ravalink) => ravalink.get_player(guild_id.into()).await,
+            None => {
+                eprintln!("Ravalink is not registered, call register_ravalink first");
+                None
+            }
+        };
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,378 @@
+use std::num::NonZero;
+use std::time::Duration;
+use ravalink_interconnect::protocol::{Command, Response};
+use crate::events::{PlayerEvent, TrackEndReason};
+use crate::batch::split_batch_response;
+use crate::filters::Filters;
+use crate::helpers::get_unix_timestamp;
+use crate::models::{LoopMode, TrackInfo};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub voice_channel_id: Option<NonZero<u64>>,
+    pub track: Option<TrackInfo>,
+    #[serde(with = "crate::helpers::millis")]
+    pub position: Duration,
+    pub paused: bool,
+    pub volume: f32,
+    pub loop_mode: LoopMode,
+    /// Most tracks the queue may hold, or `None` for no limit.
+    #[serde(default)]
+    pub max_queue_len: Option<usize>,
+    pub filters: Filters,
+    /// Playback speed from the timescale filter, used to interpolate the position.
+    pub speed: f64,
+    #[serde(with = "crate::helpers::millis")]
+    pub updated_at: Duration,
+    /// Node clock minus bot clock, in milliseconds, measured on the last response.
+    pub clock_skew: i64,
+    /// Where the last `play_with` asked its track to start, until the node
+    /// reports that it has.
+    #[serde(skip)]
+    pub(crate) pending_start: Option<Duration>,
+}
+
+/// Player state as the node reports it when a player is reattached.
+#[derive(Deserialize)]
+struct NodePlayerState {
+    voice_channel_id: Option<NonZero<u64>>,
+    track: Option<TrackInfo>,
+    #[serde(with = "crate::helpers::millis")]
+    position: Duration,
+    paused: bool,
+    volume: f32,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        PlayerState {
+            voice_channel_id: None,
+            track: None,
+            position: Duration::ZERO,
+            paused: false,
+            volume: 1.0,
+            loop_mode: LoopMode::Off,
+            max_queue_len: None,
+            filters: Filters::default(),
+            speed: 1.0,
+            updated_at: get_unix_timestamp(),
+            clock_skew: 0,
+            pending_start: None,
+        }
+    }
+}
+
+/// How long before its arrival a node timestamp may place a message. Older
+/// stamps are taken to come from a drifting clock.
+const MAX_MESSAGE_AGE: Duration = Duration::from_secs(5);
+
+/// A node timestamp in milliseconds. The node reports whole seconds, so it is
+/// taken to be halfway through the second it reported.
+fn node_millis(timestamp: u64) -> i64 {
+    timestamp as i64 * 1000 + 500
+}
+
+impl PlayerState {
+    /// Estimates the playback position at `now` from the last reported position.
+    /// The estimate never runs past the end of a track with a known duration.
+    pub fn interpolated_position(&self, now: Duration) -> Duration {
+        let track = match &self.track {
+            Some(track) if !self.paused => track,
+            _ => return self.position,
+        };
+        let elapsed = now.saturating_sub(self.updated_at);
+        let position = self.position + elapsed.mul_f64(self.speed.max(0.0));
+        if track.is_stream || track.duration.is_zero() {
+            position
+        } else {
+            position.min(track.duration)
+        }
+    }
+
+    /// Records the clock skew from a request sent at `sent_at` and answered at
+    /// `received_at` (both bot time) with a response stamped `node_timestamp`.
+    pub(crate) fn record_clock_skew(&mut self, sent_at: Duration, received_at: Duration, node_timestamp: u64) {
+        let midpoint = (sent_at + received_at) / 2;
+        self.clock_skew = node_millis(node_timestamp) - midpoint.as_millis() as i64;
+    }
+
+    /// Converts a node timestamp to bot time, using the last measured skew.
+    /// The result is kept between `MAX_MESSAGE_AGE` before `now` and `now`.
+    fn local_time(&self, node_timestamp: u64, now: Duration) -> Duration {
+        let millis = node_millis(node_timestamp) - self.clock_skew;
+        let at = Duration::from_millis(millis.max(0) as u64);
+        at.clamp(now.saturating_sub(MAX_MESSAGE_AGE), now)
+    }
+
+    /// Moves the interpolation anchor to when the node stamped a message.
+    fn resync(&mut self, node_timestamp: u64, now: Duration) {
+        let at = self.local_time(node_timestamp, now).max(self.updated_at);
+        self.position = self.interpolated_position(at);
+        self.updated_at = at;
+    }
+
+    /// Applies a command the node has acknowledged with `response`, received
+    /// at `now`.
+    pub(crate) fn apply_command(
+        &mut self,
+        command: &Command,
+        voice_channel_id: Option<NonZero<u64>>,
+        response: &Response,
+        now: Duration,
+    ) {
+        self.resync(response.timestamp, now);
+
+        match command {
+            Command::Connect | Command::VoiceUpdate { .. } => {
+                self.voice_channel_id = voice_channel_id;
+            }
+            Command::Reattach { .. } => {
+                if let Ok(node) = serde_json::from_value::<NodePlayerState>(response.data.clone()) {
+                    self.voice_channel_id = node.voice_channel_id;
+                    self.track = node.track;
+                    self.position = node.position;
+                    self.paused = node.paused;
+                    self.volume = node.volume;
+                }
+            }
+            Command::Disconnect | Command::Destroy => {
+                self.voice_channel_id = None;
+                self.track = None;
+                self.position = Duration::ZERO;
+                self.paused = false;
+                self.pending_start = None;
+            }
+            Command::Stop => {
+                self.track = None;
+                self.position = Duration::ZERO;
+                self.paused = false;
+                self.pending_start = None;
+            }
+            Command::Play { start_time, .. } => {
+                if let Ok(Some(track)) = serde_json::from_value::<Option<TrackInfo>>(response.data.clone()) {
+                    let start = start_time.map(Duration::from_millis);
+                    self.track = Some(track);
+                    self.position = start.unwrap_or(Duration::ZERO);
+                    self.paused = false;
+                    self.pending_start = start;
+                }
+            }
+            Command::Crossfade { .. } => {
+                self.track = serde_json::from_value(response.data.clone()).ok();
+                self.position = Duration::ZERO;
+                self.paused = false;
+                self.pending_start = None;
+            }
+            Command::SetVolume { volume } => {
+                self.volume = *volume;
+            }
+            Command::Loop => {}
+            Command::SeekToPosition { position } => {
+                self.position = Duration::from_millis(*position);
+            }
+            Command::Resume => {
+                self.paused = false;
+            }
+            Command::Pause => {
+                self.paused = true;
+            }
+            Command::Resolve { .. } => {}
+            Command::SetFilters { filters } => {
+                if let Ok(filters) = serde_json::from_value::<Filters>(filters.clone()) {
+                    self.speed = filters.playback_speed();
+                    self.filters = filters;
+                }
+            }
+            Command::Batch { commands } => {
+                for (command, response) in commands.iter().zip(split_batch_response(response, commands.len())) {
+                    self.apply_command(command, voice_channel_id, &response, now);
+                }
+            }
+        }
+    }
+
+    /// Applies an event the node stamped `node_timestamp`, received at `now`.
+    pub(crate) fn apply_event(&mut self, event: &PlayerEvent, node_timestamp: u64, now: Duration) {
+        self.resync(node_timestamp, now);
+
+        match event {
+            PlayerEvent::TrackStart { track } => {
+                if track.is_some() {
+                    self.track = track.clone();
+                }
+                // The start event may follow the response to `play_with`,
+                // which already knows where the track starts.
+                self.position = self.pending_start.take().unwrap_or(Duration::ZERO);
+                self.paused = false;
+            }
+            PlayerEvent::TrackEnd { reason, .. } => {
+                // A replaced track ends after its successor has already started.
+                if *reason != TrackEndReason::Replaced {
+                    self.track = None;
+                    self.position = Duration::ZERO;
+                    self.pending_start = None;
+                }
+            }
+            PlayerEvent::PositionUpdate { position } => {
+                self.position = *position;
+            }
+            PlayerEvent::VoiceClosed { .. } => {
+                self.voice_channel_id = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track;
+    use serde_json::{json, Value};
+
+    /// Bot time in the tests, which matches node time unless a test says otherwise.
+    const NOW: Duration = Duration::from_secs(1_000_000);
+
+    fn play(state: &mut PlayerState, url: &str, start_time: Option<u64>) {
+        let response = Response {
+            job_id: "job".to_string(),
+            guild_id: NonZero::new(1).unwrap(),
+            timestamp: NOW.as_secs(),
+            error: None,
+            data: json!(track(url)),
+        };
+        let command = Command::Play { url: url.to_string(), start_time, end_time: None, no_replace: false };
+        state.apply_command(&command, None, &response, NOW);
+    }
+
+    fn event(state: &mut PlayerState, event: PlayerEvent) {
+        state.apply_event(&event, NOW.as_secs(), NOW);
+    }
+
+    fn playing(position: Duration) -> PlayerState {
+        PlayerState { track: Some(track("a")), position, updated_at: NOW, ..PlayerState::default() }
+    }
+
+    #[test]
+    fn position_advances_with_playback_speed() {
+        let mut state = playing(Duration::from_secs(10));
+        assert_eq!(state.interpolated_position(NOW + Duration::from_secs(2)), Duration::from_secs(12));
+
+        state.speed = 1.5;
+        assert_eq!(state.interpolated_position(NOW + Duration::from_secs(2)), Duration::from_secs(13));
+
+        state.paused = true;
+        assert_eq!(state.interpolated_position(NOW + Duration::from_secs(2)), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn position_stops_at_the_end_of_the_track() {
+        let mut state = playing(Duration::from_secs(170));
+        assert_eq!(state.interpolated_position(NOW + Duration::from_secs(60)), Duration::from_secs(180));
+
+        // Streams have no end to stop at.
+        state.track.as_mut().unwrap().is_stream = true;
+        assert_eq!(state.interpolated_position(NOW + Duration::from_secs(60)), Duration::from_secs(230));
+    }
+
+    #[test]
+    fn events_are_anchored_to_the_node_clock() {
+        let mut state = PlayerState { updated_at: Duration::ZERO, ..playing(Duration::ZERO) };
+        // The node stamps second 105 while the bot is around 100.1s.
+        state.record_clock_skew(Duration::from_secs(100), Duration::from_millis(100_200), 105);
+        assert_eq!(state.clock_skew, 5_400);
+
+        // Node second 106 is bot time 101.1s; the event arrives half a second later.
+        let received = Duration::from_millis(101_600);
+        state.apply_event(&PlayerEvent::PositionUpdate { position: Duration::from_secs(20) }, 106, received);
+        assert_eq!(state.updated_at, Duration::from_millis(101_100));
+        assert_eq!(state.interpolated_position(received), Duration::from_millis(20_500));
+    }
+
+    #[test]
+    fn node_timestamps_are_kept_near_the_arrival_time() {
+        let mut state = PlayerState { updated_at: Duration::ZERO, ..PlayerState::default() };
+        let position = PlayerEvent::PositionUpdate { position: Duration::from_secs(20) };
+
+        state.apply_event(&position, 0, NOW);
+        assert_eq!(state.updated_at, NOW - MAX_MESSAGE_AGE);
+        state.apply_event(&position, NOW.as_secs() + 3600, NOW + Duration::from_secs(1));
+        assert_eq!(state.updated_at, NOW + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn track_start_keeps_the_requested_start_offset() {
+        let mut state = PlayerState::default();
+        play(&mut state, "a", Some(30_000));
+        event(&mut state, PlayerEvent::TrackStart { track: Some(track("a")) });
+        assert_eq!(state.position, Duration::from_secs(30));
+
+        // The offset belongs to that one track.
+        event(&mut state, PlayerEvent::TrackEnd { track: Some(track("a")), reason: TrackEndReason::Finished });
+        event(&mut state, PlayerEvent::TrackStart { track: Some(track("b")) });
+        assert_eq!(state.position, Duration::ZERO);
+    }
+
+    #[test]
+    fn track_start_ahead_of_the_response_does_not_leak_the_offset() {
+        let mut state = PlayerState::default();
+        event(&mut state, PlayerEvent::TrackStart { track: Some(track("a")) });
+        play(&mut state, "a", Some(30_000));
+        assert_eq!(state.position, Duration::from_secs(30));
+
+        event(&mut state, PlayerEvent::TrackEnd { track: Some(track("a")), reason: TrackEndReason::Finished });
+        event(&mut state, PlayerEvent::TrackStart { track: Some(track("b")) });
+        assert_eq!(state.position, Duration::ZERO);
+    }
+
+    fn acknowledge(state: &mut PlayerState, command: Command, voice_channel_id: Option<NonZero<u64>>) {
+        let response = Response {
+            job_id: "job".to_string(),
+            guild_id: NonZero::new(1).unwrap(),
+            timestamp: NOW.as_secs(),
+            error: None,
+            data: Value::Null,
+        };
+        state.apply_command(&command, voice_channel_id, &response, NOW);
+    }
+
+    #[test]
+    fn acknowledged_commands_update_the_state() {
+        let mut state = PlayerState::default();
+        acknowledge(&mut state, Command::Connect, NonZero::new(5));
+        play(&mut state, "a", None);
+        acknowledge(&mut state, Command::SetVolume { volume: 0.5 }, None);
+        acknowledge(&mut state, Command::Pause, None);
+        assert_eq!(state.voice_channel_id, NonZero::new(5));
+        assert_eq!(state.track, Some(track("a")));
+        assert_eq!(state.volume, 0.5);
+        assert!(state.paused);
+
+        acknowledge(&mut state, Command::Resume, None);
+        assert!(!state.paused);
+        acknowledge(&mut state, Command::Stop, None);
+        assert_eq!(state.track, None);
+        assert_eq!(state.voice_channel_id, NonZero::new(5));
+        acknowledge(&mut state, Command::Disconnect, None);
+        assert_eq!(state.voice_channel_id, None);
+    }
+
+    #[test]
+    fn a_replaced_track_does_not_clear_its_successor() {
+        let mut state = PlayerState::default();
+        play(&mut state, "b", None);
+        event(&mut state, PlayerEvent::TrackEnd { track: Some(track("a")), reason: TrackEndReason::Replaced });
+        assert_eq!(state.track, Some(track("b")));
+
+        event(&mut state, PlayerEvent::TrackEnd { track: Some(track("b")), reason: TrackEndReason::Stopped });
+        assert_eq!(state.track, None);
+    }
+
+    #[test]
+    fn a_closed_voice_connection_clears_the_channel() {
+        let mut state = PlayerState { voice_channel_id: NonZero::new(5), ..PlayerState::default() };
+        event(&mut state, PlayerEvent::VoiceClosed { code: Some(4014), reason: None });
+        assert_eq!(state.voice_channel_id, None);
+    }
+}
@@ -0,0 +1,118 @@
+//! A stand-in for the node, for tests that need a player to send commands.
+
+use crate::background::processor::RavalinkIPC;
+use crate::helpers::get_timestamp;
+use crate::middleware::MiddlewareChain;
+use crate::models::TrackInfo;
+use crate::PlayerObject;
+use ravalink_interconnect::protocol::{Command, Event, EventType, Message, Response};
+use serde_json::{json, Value};
+use std::num::NonZero;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, Sender};
+
+/// How the fake node answers a command: with the response data, or with an
+/// error code such as `"unsupported"`.
+pub(crate) type Reply = Result<Value, &'static str>;
+
+pub(crate) struct FakeNode {
+    pub(crate) tx: Sender<RavalinkIPC>,
+    received: Arc<Mutex<Vec<Command>>>,
+}
+
+impl FakeNode {
+    /// Starts a node that answers every request with `reply`.
+    pub(crate) fn spawn(reply: impl Fn(&Command) -> Reply + Send + 'static) -> Self {
+        let (tx, mut rx) = broadcast::channel(64);
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = received.clone();
+        tokio::spawn(async move {
+            while let Ok(ipc) = rx.recv().await {
+                let RavalinkIPC::Message(message) = ipc else {
+                    continue;
+                };
+                let (Message::Request(request), Some(response_tx)) = (message.message, message.response_tx) else {
+                    continue;
+                };
+                log.lock().unwrap().push(request.command.clone());
+
+                let (error, data) = match reply(&request.command) {
+                    Ok(data) => (None, data),
+                    Err(code) => (Some(code.to_string()), json!({ "code": code })),
+                };
+                let response = Message::Response(Response {
+                    job_id: request.job_id,
+                    guild_id: request.guild_id,
+                    timestamp: get_timestamp(),
+                    error,
+                    data,
+                });
+                let _ = response_tx.send(RavalinkIPC::create_server_response(response));
+            }
+        });
+
+        FakeNode { tx, received }
+    }
+
+    /// Creates a player for guild 1 that talks to this node and is already
+    /// connected to a voice channel.
+    pub(crate) async fn player(&self) -> PlayerObject {
+        self.player_with(Arc::new(Vec::new())).await
+    }
+
+    /// Like `player`, with `middleware` around every command.
+    pub(crate) async fn player_with(&self, middleware: MiddlewareChain) -> PlayerObject {
+        let player = PlayerObject::new(NonZero::new(1).unwrap(), self.tx.clone(), middleware, None, Weak::new())
+            .await
+            .unwrap();
+        player.inner.state.send_modify(|s| s.voice_channel_id = NonZero::new(2));
+        player
+    }
+
+    /// The commands received so far, in order.
+    pub(crate) fn received(&self) -> Vec<Command> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Waits up to a second for the node to have received `count` commands,
+    /// then returns them.
+    pub(crate) async fn wait_for(&self, count: usize) -> Vec<Command> {
+        for _ in 0..100 {
+            if self.received.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        self.received()
+    }
+}
+
+/// Answers `Play` with the track it plays and everything else with `null`.
+pub(crate) fn play_reply(command: &Command) -> Reply {
+    match command {
+        Command::Play { url, .. } => Ok(json!(track(url))),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// A three minute track at `url`.
+pub(crate) fn track(url: &str) -> TrackInfo {
+    TrackInfo {
+        url: url.to_string(),
+        title: url.to_string(),
+        author: "author".to_string(),
+        duration: Duration::from_secs(180),
+        artwork_url: None,
+        source: "http".to_string(),
+        is_seekable: true,
+        is_stream: false,
+    }
+}
+
+/// Delivers a node event to `player`, as the processor would.
+pub(crate) fn emit(player: &PlayerObject, event_type: EventType, data: Value) {
+    let event = Event { guild_id: player.guild_id(), event_type, timestamp: get_timestamp(), data };
+    let _ = player.inner.tx.send(RavalinkIPC::create_server_response(Message::Event(event)));
+}